@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use serde::de::IgnoredAny;
+use serde_bencode::value::Value;
+
+// Arbitrary input must only ever produce an `Err`, never a panic, whether it
+// is decoded into a `Value` or skipped over.
+fuzz_target!(|data: &[u8]| {
+    let _ = serde_bencode::from_bytes::<Value>(data);
+    let _ = serde_bencode::from_bytes::<IgnoredAny>(data);
+});
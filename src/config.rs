@@ -0,0 +1,143 @@
+// Options controlling how strictly bencode is read and written. The defaults
+// follow the BitTorrent specification to the letter; individual checks can be
+// relaxed for interoperability with older or sloppier encoders.
+//
+//     let config = Config::new().lenient_integers(true);
+//     let mut deserializer = Deserializer::from_slice(input).with_config(config);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub(crate) lenient_integers: bool,
+    pub(crate) bools_as_integers: bool,
+    pub(crate) recursion_limit: usize,
+    pub(crate) limits: Limits,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config {
+            lenient_integers: false,
+            bools_as_integers: true,
+            recursion_limit: 128,
+            limits: Limits::new(),
+        }
+    }
+
+    // Accept integers with leading zeros (`i03e`) and negative zero (`i-0e`).
+    // Such input is never produced by a conforming encoder, and re-encoding
+    // it yields different bytes, so leave this off when the original bytes
+    // matter, e.g. for infohash computations.
+    pub fn lenient_integers(mut self, lenient: bool) -> Self {
+        self.lenient_integers = lenient;
+        self
+    }
+
+    // Bencode has no boolean type, but the de facto convention (the torrent
+    // `private` flag, the DHT `ro` flag, ...) is `i0e` for false and `i1e`
+    // for true, which is what is read and written by default. Disable this to
+    // have `bool` rejected with `ErrorKind::BoolUnsupported` on both ends instead.
+    //
+    // Only `bool`s read by this crate's deserializer are decoded from integers.
+    // Inside `#[serde(flatten)]`, untagged and internally tagged enums serde
+    // reads `i1e` as an integer first and then refuses it as a `bool`; use
+    // `de::bool_from_integer` for those fields.
+    pub fn bools_as_integers(mut self, enabled: bool) -> Self {
+        self.bools_as_integers = enabled;
+        self
+    }
+
+    // Maximum number of nested lists and dictionaries (enum variants holding
+    // data count as one more level) before giving up with
+    // `ErrorKind::RecursionLimitExceeded`. Nesting is handled by recursion,
+    // so without a limit a few hundred thousand `l`s are enough to overflow
+    // the stack. Defaults to 128, which is plenty for anything in the
+    // BitTorrent ecosystem. Applies to serializing as well.
+    pub fn recursion_limit(mut self, limit: usize) -> Self {
+        self.recursion_limit = limit;
+        self
+    }
+
+    // Resource limits enforced while deserializing. See `Limits`.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
+}
+
+// Bounds on how much a single document may make the deserializer read and
+// hand out, for parsing untrusted input such as tracker responses or DHT
+// packets. Every limit is off by default. When streaming, each value is held
+// to them on its own.
+//
+//     let limits = Limits::new()
+//         .max_input_size(64 << 20)
+//         .max_string_length(1 << 20)
+//         .max_entries(10_000)
+//         .max_allocated(16 << 20);
+//     let config = Config::new().limits(limits);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub(crate) max_input_size: usize,
+    pub(crate) max_string_length: usize,
+    pub(crate) max_entries: usize,
+    pub(crate) max_allocated: usize,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Limits {
+            max_input_size: usize::MAX,
+            max_string_length: usize::MAX,
+            max_entries: usize::MAX,
+            max_allocated: usize::MAX,
+        }
+    }
+
+    // Most bytes a document may span. Byte strings are checked against their
+    // length prefix before they are read, so a reader is never drained past
+    // the limit. Exceeding it fails with `ErrorKind::InputTooLarge`. Whatever
+    // is deserialized from a slice is bounded by this too, even when copied.
+    pub fn max_input_size(mut self, max: usize) -> Self {
+        self.max_input_size = max;
+        self
+    }
+
+    // Longest byte string accepted, checked against the length prefix before
+    // anything is read. Exceeding it fails with `ErrorKind::StringTooLong`.
+    // Applies to skipped values too.
+    pub fn max_string_length(mut self, max: usize) -> Self {
+        self.max_string_length = max;
+        self
+    }
+
+    // Most elements a single list, or entries a single dictionary, may have.
+    // Exceeding it fails with `ErrorKind::TooManyEntries`. Applies to skipped
+    // values too.
+    pub fn max_entries(mut self, max: usize) -> Self {
+        self.max_entries = max;
+        self
+    }
+
+    // Budget for the combined length of the byte strings (keys included)
+    // copied out of the input while deserializing one value, which is every
+    // byte string read through `from_reader`. Byte strings borrowed from a
+    // slice don't count; bound those with `max_input_size`. Neither do skipped
+    // values, except for a `RawValue` read through `from_reader`, which is
+    // charged for every byte it copies. Exceeding it fails with
+    // `ErrorKind::AllocationLimitExceeded` before the byte string is read.
+    pub fn max_allocated(mut self, max: usize) -> Self {
+        self.max_allocated = max;
+        self
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::new()
+    }
+}
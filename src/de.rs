@@ -1,128 +1,539 @@
-use std::ops::{AddAssign, MulAssign, Neg};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::str;
 
 use serde::Deserialize;
+use serde::de::DeserializeOwned;
 use serde::de::{
-    // EnumAccess, IntoDeserializer, VariantAccess
-    self, DeserializeSeed, Visitor, MapAccess, SeqAccess,
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    Unexpected, VariantAccess, Visitor,
 };
+use serde::forward_to_deserialize_any;
 
-use crate::error::{Error, Result};
+use crate::config::Config;
+use crate::error::{Error, ErrorKind, Result};
+use crate::read::{self, Reference};
+use crate::value::RAW_VALUE_TOKEN;
 
-pub struct Deserializer<'de> {
-    // This string starts with the input data and characters are truncated off
-    // the beginning as data is parsed.
-    input: &'de str,
+pub use crate::read::{IoRead, Read, SliceRead};
+
+pub struct Deserializer<R> {
+    // Where the input comes from. Bencode byte strings may hold arbitrary
+    // binary data, so the input is not required to be valid UTF-8.
+    read: R,
+    // Byte strings that can't be borrowed from the input are copied here.
+    scratch: Vec<u8>,
+    config: Config,
+    // How many more lists and dictionaries may be opened before hitting the
+    // configured recursion limit.
+    remaining_depth: usize,
+    // Where the current value starts and the combined length of the byte
+    // strings copied for it so far, checked against `Limits`.
+    value_start: usize,
+    allocated: usize,
+    // Where the raw value being copied out of a reader starts, so that the
+    // copy can be charged as it grows.
+    raw_start: Option<usize>,
+}
+
+impl<'de, R> Deserializer<R>
+where
+    R: read::Read<'de>,
+{
+    // Create a bencode deserializer from one of the possible serde_bencode
+    // input sources. Typically it is more convenient to use one of these
+    // methods instead:
+    //
+    //   - Deserializer::from_slice
+    //   - Deserializer::from_str
+    //   - Deserializer::from_reader
+    pub fn new(read: R) -> Self {
+        let config = Config::default();
+        Deserializer {
+            read,
+            scratch: Vec::new(),
+            config,
+            remaining_depth: config.recursion_limit,
+            value_start: 0,
+            allocated: 0,
+            raw_start: None,
+        }
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self.remaining_depth = config.recursion_limit;
+        self
+    }
+
+    // Call once the value has been deserialized to make sure the whole input
+    // was consumed.
+    pub fn end(&mut self) -> Result<()> {
+        match self.peek()? {
+            Some(_) => Err(self.error(ErrorKind::TrailingCharacters)),
+            None => Ok(()),
+        }
+    }
+
+    // Turn this deserializer into an iterator over a sequence of
+    // concatenated values. See `StreamDeserializer`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn into_iter<T>(self) -> StreamDeserializer<'de, R, T>
+    where
+        T: Deserialize<'de>,
+    {
+        StreamDeserializer {
+            de: self,
+            offset: 0,
+            failed: false,
+            output: PhantomData,
+            lifetime: PhantomData,
+        }
+    }
+}
+
+// By convention, `Deserializer` constructors are named like `from_xyz`.
+// That way basic use cases are satisfied by something like
+// `serde_json::from_str(...)` while advanced use cases that require a
+// deserializer can make one with `serde_json::Deserializer::from_str(...)`.
+impl<'a> Deserializer<SliceRead<'a>> {
+    pub fn from_slice(input: &'a [u8]) -> Self {
+        Deserializer::new(SliceRead::new(input))
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'a str) -> Self {
+        Deserializer::from_slice(input.as_bytes())
+    }
 }
 
-impl<'de> Deserializer<'de> {
-    // By convention, `Deserializer` constructors are named like `from_xyz`.
-    // That way basic use cases are satisfied by something like
-    // `serde_json::from_str(...)` while advanced use cases that require a
-    // deserializer can make one with `serde_json::Deserializer::from_str(...)`.
-    pub fn from_str(input: &'de str) -> Self {
-        Deserializer { input }
+impl<R> Deserializer<IoRead<R>>
+where
+    R: io::Read,
+{
+    pub fn from_reader(reader: R) -> Self {
+        Deserializer::new(IoRead::new(reader))
     }
 }
 
-impl<'de> Deserializer<'de> {
-    // Look at the first character in the input without consuming it.
-    fn peek_char(&mut self) -> Result<char> {
-        self.input.chars().next().ok_or(Error::Eof)
+impl<'de, R: Read<'de>> Deserializer<R> {
+    // An error pointing at the next unread byte.
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::at(kind, self.read.byte_offset())
     }
 
-    // Consume the first character in the input.
-    fn next_char(&mut self) -> Result<char> {
-        let ch = self.peek_char()?;
-        self.input = &self.input[ch.len_utf8()..];
+    // Look at the next byte in the input without consuming it. `None` at the
+    // end of input.
+    fn peek(&mut self) -> Result<Option<u8>> {
+        let ch = self.read.peek()?;
+        if ch.is_some() {
+            let offset = self.read.byte_offset();
+            if offset - self.value_start >= self.config.limits.max_input_size {
+                return Err(Error::at(ErrorKind::InputTooLarge, offset));
+            }
+        }
         Ok(ch)
     }
 
-    fn parse_signed<T>(&mut self) -> Result<T>
-        where T: Neg<Output = T> + AddAssign<T> + MulAssign<T> + From<i8> + std::fmt::Display,
-    {
-        // TODO: Invalidate leading 0.
-        if self.next_char()? != 'i' {
-            return Err(Error::ExpectedI);
+    fn peek_byte(&mut self) -> Result<u8> {
+        match self.peek()? {
+            Some(ch) => Ok(ch),
+            None => Err(self.error(ErrorKind::Eof)),
         }
+    }
 
-        let mut int = match self.next_char()? {
-            ch @ '0'..='9' => T::from(ch as i8 - b'0' as i8),
-            '-' => match self.next_char()? {
-                ch @ '0'..='9' => - T::from(ch as i8 - b'0' as i8),
-                _ => { 
-                    return Err(Error::ExpectedInteger);
-                }
-            },
+    // Called on entering a list or dictionary, with the offset of its opening
+    // byte. Must be paired with `leave_container`, on error too.
+    fn enter_container(&mut self, start: usize) -> Result<()> {
+        if self.remaining_depth == 0 {
+            return Err(Error::at(ErrorKind::RecursionLimitExceeded, start));
+        }
+        self.remaining_depth -= 1;
+        Ok(())
+    }
+
+    fn leave_container(&mut self) {
+        self.remaining_depth += 1;
+    }
+
+    // Consume the next byte in the input if it is `expected`. Otherwise the
+    // byte is left in place so the error points right at it.
+    fn expect_byte(&mut self, expected: u8, kind: ErrorKind) -> Result<()> {
+        if self.peek_byte()? == expected {
+            self.read.discard();
+            Ok(())
+        } else {
+            Err(self.error(kind))
+        }
+    }
+
+    // Parse an `i<digits>e` integer into its sign and magnitude. The
+    // magnitude is accumulated in a `u128` with overflow checks so that every
+    // primitive integer type can be range checked against it afterwards.
+    fn parse_integer(&mut self) -> Result<(bool, u128)> {
+        let start = self.read.byte_offset();
+        self.expect_byte(b'i', ErrorKind::ExpectedI)?;
+
+        let negative = self.peek_byte()? == b'-';
+        if negative {
+            self.read.discard();
+        }
+
+        let mut int = match self.peek_byte()? {
+            ch @ b'0'..=b'9' => {
+                self.read.discard();
+                self.check_canonical(start, negative, ch)?;
+                u128::from(ch - b'0')
+            }
             _ => {
-                return Err(Error::ExpectedInteger);
+                return Err(self.error(ErrorKind::ExpectedInteger));
             }
         };
 
         loop {
-            match self.input.chars().next() {
-                Some(ch @ '0'..= '9') => {
-                    self.input = &self.input[1..];
-                    int *= T::from(10);
-                    int += T::from(ch as i8 - b'0' as i8);
+            match self.peek()? {
+                Some(ch @ b'0'..=b'9') => {
+                    self.read.discard();
+                    int = match int.checked_mul(10)
+                        .and_then(|int| int.checked_add(u128::from(ch - b'0'))) {
+                        Some(int) => int,
+                        None => return Err(Error::at(ErrorKind::IntegerOverflow, start)),
+                    };
                 }
-                Some('e') => {
-                    self.input = &self.input[1..];
-                    return Ok(int);
+                Some(b'e') => {
+                    self.read.discard();
+                    return Ok((negative, int));
                 }
-                _ => {
-                    return Err(Error::UnexpectedChar)
+                Some(_) => {
+                    return Err(self.error(ErrorKind::UnexpectedChar))
+                }
+                None => {
+                    return Err(self.error(ErrorKind::Eof))
                 }
             }
         }
     }
 
-    fn parse_string(&mut self) -> Result<&'de str> {
-        let mut len = match self.next_char()? {
-            ch @ '0'..='9' => usize::from(ch as u8 - b'0'),
+    // The spec only allows one encoding per integer: no leading zeros and no
+    // negative zero. Called with the first digit already consumed; errors
+    // point at the start of the integer.
+    fn check_canonical(&mut self, start: usize, negative: bool, first: u8) -> Result<()> {
+        if first != b'0' || self.config.lenient_integers {
+            return Ok(());
+        }
+
+        match self.peek()? {
+            Some(b'0'..=b'9') => Err(Error::at(ErrorKind::LeadingZero, start)),
+            _ if negative => Err(Error::at(ErrorKind::NegativeZero, start)),
+            _ => Ok(()),
+        }
+    }
+
+    fn parse_signed<T>(&mut self) -> Result<T>
+        where T: TryFrom<i128>,
+    {
+        let start = self.read.byte_offset();
+        let (negative, int) = self.parse_integer()?;
+        let overflow = || Error::at(ErrorKind::IntegerOverflow, start);
+        let int = if negative {
+            // `i128::MIN` has no positive counterpart, so negate with
+            // wrapping to let exactly that magnitude through.
+            if int > i128::MIN.unsigned_abs() {
+                return Err(overflow());
+            }
+            (int as i128).wrapping_neg()
+        } else {
+            i128::try_from(int).map_err(|_| overflow())?
+        };
+        T::try_from(int).map_err(|_| overflow())
+    }
+
+    fn parse_unsigned<T>(&mut self) -> Result<T>
+        where T: TryFrom<u128>,
+    {
+        let start = self.read.byte_offset();
+        let (negative, int) = self.parse_integer()?;
+        if negative && int != 0 {
+            return Err(Error::at(ErrorKind::IntegerOverflow, start));
+        }
+        T::try_from(int).map_err(|_| Error::at(ErrorKind::IntegerOverflow, start))
+    }
+
+    // Parse the `<len>:` prefix of a byte string.
+    fn parse_length(&mut self) -> Result<usize> {
+        let start = self.read.byte_offset();
+        let mut len = match self.peek_byte()? {
+            ch @ b'0'..=b'9' => {
+                self.read.discard();
+                usize::from(ch - b'0')
+            }
             _ => {
-                return Err(Error::ExpectedInteger);
+                return Err(self.error(ErrorKind::ExpectedInteger));
             }
         };
 
         loop {
-            match self.input.chars().next() {
-                Some(ch @ '0'..='9') => {
-                    self.input = &self.input[1..];
-                    len *= 10 as usize;
-                    len += usize::from(ch as u8 - b'0');
+            match self.peek()? {
+                Some(ch @ b'0'..=b'9') => {
+                    self.read.discard();
+                    len = match len.checked_mul(10)
+                        .and_then(|len| len.checked_add(usize::from(ch - b'0'))) {
+                        Some(len) => len,
+                        None => return Err(Error::at(ErrorKind::LengthOverflow, start)),
+                    };
                 }
-                Some(':') => {
-                    self.input = &self.input[1..];
-                    break;
+                Some(b':') => {
+                    self.read.discard();
+                    if len > self.config.limits.max_string_length {
+                        return Err(Error::at(ErrorKind::StringTooLong, start));
+                    }
+                    let size = self.read.byte_offset() - self.value_start;
+                    if len > self.config.limits.max_input_size - size {
+                        return Err(Error::at(ErrorKind::InputTooLarge, start));
+                    }
+                    return Ok(len);
                 }
                 _ => {
-                    return Err(Error::ExpectedColon)
+                    return Err(self.error(ErrorKind::ExpectedColon))
                 }
             }
         }
+    }
+
+    // Parse a `<len>:<bytes>` byte string. The contents are borrowed straight
+    // out of the input when reading from a slice, and copied into the scratch
+    // buffer when reading from a stream.
+    fn parse_string<'s>(&'s mut self) -> Result<Reference<'de, 's, [u8]>> {
+        let start = self.read.byte_offset();
+        let len = self.parse_length()?;
+
+        if R::COPIES {
+            self.allocated = self.allocated.saturating_add(len);
+            if self.allocated > self.config.limits.max_allocated {
+                return Err(Error::at(ErrorKind::AllocationLimitExceeded, start));
+            }
+        }
+
+        self.read.parse_bytes(len, &mut self.scratch)
+    }
+
+    // Byte strings are only validated as UTF-8 when the caller asks for a
+    // `str` or `String`.
+    fn parse_str<'s>(&'s mut self) -> Result<Reference<'de, 's, str>> {
+        let start = self.read.byte_offset();
+        match self.parse_string()? {
+            Reference::Borrowed(bytes) => str::from_utf8(bytes).map(Reference::Borrowed),
+            Reference::Copied(bytes) => str::from_utf8(bytes).map(Reference::Copied),
+        }
+        .map_err(|_| Error::at(ErrorKind::InvalidUtf8, start))
+    }
 
-        let s = &self.input[..len];
-        
-        self.input = &self.input[len..];
+    // Skip over one complete value without materializing any of it. Byte
+    // strings are jumped over using their length prefix, so the cost is
+    // proportional to the number of bytes skipped and nothing is allocated.
+    // Skipped data is held to the same rules as decoded data: containers
+    // count towards the recursion limit, which also bounds how deep this
+    // recurses, and dictionaries must alternate byte string keys and values.
+    fn ignore_value(&mut self) -> Result<()> {
+        match self.peek_byte()? {
+            b'i' => self.ignore_integer(),
+            b'0'..=b'9' => self.ignore_string(),
+            b'l' => {
+                self.enter_container(self.read.byte_offset())?;
+                self.read.discard();
+                let result = self.ignore_list();
+                self.leave_container();
+                result
+            }
+            b'd' => {
+                self.enter_container(self.read.byte_offset())?;
+                self.read.discard();
+                let result = self.ignore_dict();
+                self.leave_container();
+                result
+            }
+            _ => Err(self.error(ErrorKind::Syntax)),
+        }
+    }
 
-        Ok(s)
+    // Skip the rest of a list whose `l` has been consumed.
+    fn ignore_list(&mut self) -> Result<()> {
+        let mut entries = 0;
+        while self.peek_byte()? != b'e' {
+            self.ignore_entry(&mut entries)?;
+            self.ignore_value()?;
+        }
+        self.read.discard();
+        Ok(())
+    }
+
+    // Skip the rest of a dictionary whose `d` has been consumed.
+    fn ignore_dict(&mut self) -> Result<()> {
+        let mut entries = 0;
+        loop {
+            match self.peek_byte()? {
+                b'e' => break,
+                b'0'..=b'9' => {
+                    self.ignore_entry(&mut entries)?;
+                    self.ignore_string()?;
+                }
+                _ => return Err(self.error(ErrorKind::KeyMustBeAString)),
+            }
+            // Every key needs a value.
+            if self.peek_byte()? == b'e' {
+                return Err(self.error(ErrorKind::Syntax));
+            }
+            self.ignore_value()?;
+        }
+        self.read.discard();
+        Ok(())
+    }
+
+    // Skip a byte string. Inside a raw value read from a reader it is copied
+    // all the same, so everything buffered up to its end is charged against
+    // `Limits::max_allocated` before it is read.
+    fn ignore_string(&mut self) -> Result<()> {
+        let start = self.read.byte_offset();
+        let len = self.parse_length()?;
+
+        if let Some(raw_start) = self.raw_start {
+            let buffered = (self.read.byte_offset() - raw_start).saturating_add(len);
+            if self.allocated.saturating_add(buffered) > self.config.limits.max_allocated {
+                return Err(Error::at(ErrorKind::AllocationLimitExceeded, start));
+            }
+        }
+
+        self.read.ignore_bytes(len)
+    }
+
+    // Count one more skipped entry against `Limits::max_entries`.
+    fn ignore_entry(&self, entries: &mut usize) -> Result<()> {
+        if *entries == self.config.limits.max_entries {
+            return Err(self.error(ErrorKind::TooManyEntries));
+        }
+        *entries += 1;
+        Ok(())
+    }
+
+    // Validate the next value the same way `ignore_value` does and hand its
+    // bytes to the visitor untouched: borrowed from a slice, copied from a
+    // reader.
+    fn deserialize_raw_value<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let start = self.read.byte_offset();
+        if R::COPIES {
+            self.raw_start = Some(start);
+        }
+        self.read.begin_raw_buffering();
+        let result = self.ignore_value();
+        self.raw_start = None;
+        let raw = self.read.end_raw_buffering(&mut self.scratch);
+        result?;
+
+        if R::COPIES {
+            self.allocated = self.allocated.saturating_add(raw.len());
+            if self.allocated > self.config.limits.max_allocated {
+                return Err(Error::at(ErrorKind::AllocationLimitExceeded, start));
+            }
+        }
+        match raw {
+            Reference::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Reference::Copied(bytes) => visitor.visit_bytes(bytes),
+        }
+    }
+
+    // Skip an integer, checking its syntax but not its range.
+    fn ignore_integer(&mut self) -> Result<()> {
+        let start = self.read.byte_offset();
+        self.expect_byte(b'i', ErrorKind::ExpectedI)?;
+
+        let negative = self.peek_byte()? == b'-';
+        if negative {
+            self.read.discard();
+        }
+
+        match self.peek_byte()? {
+            ch @ b'0'..=b'9' => {
+                self.read.discard();
+                self.check_canonical(start, negative, ch)?;
+            }
+            _ => {
+                return Err(self.error(ErrorKind::ExpectedInteger));
+            }
+        }
+
+        loop {
+            match self.peek_byte()? {
+                b'0'..=b'9' => self.read.discard(),
+                b'e' => {
+                    self.read.discard();
+                    return Ok(());
+                }
+                _ => {
+                    return Err(self.error(ErrorKind::UnexpectedChar));
+                }
+            }
+        }
+    }
+}
+
+// Hand a byte string to a visitor as a `str` whenever it is valid UTF-8, so
+// that string-like types don't need to support bytes, and as bytes otherwise.
+fn visit_bytes_or_str<'de, V>(bytes: Reference<'de, '_, [u8]>, visitor: V) -> Result<V::Value>
+where
+    V: Visitor<'de>,
+{
+    match bytes {
+        Reference::Borrowed(bytes) => match str::from_utf8(bytes) {
+            Ok(s) => visitor.visit_borrowed_str(s),
+            Err(_) => visitor.visit_borrowed_bytes(bytes),
+        },
+        Reference::Copied(bytes) => match str::from_utf8(bytes) {
+            Ok(s) => visitor.visit_str(s),
+            Err(_) => visitor.visit_bytes(bytes),
+        },
     }
 }
 
 /// Seem to need to implement Access for these guys instead of the deserializer.
-struct ColonSeparated<'a, 'de: 'a> {
-    de: &'a mut Deserializer<'de>,
+///
+/// Also keeps track of where in the container it is, so that errors coming
+/// out of an entry can be given its offset and a path like `files[17].path`.
+struct ColonSeparated<'a, R: 'a> {
+    de: &'a mut Deserializer<R>,
+    // Number of entries seen so far, which is also the index of the next
+    // list element.
+    index: usize,
+    // The most recent dictionary key, for error paths.
+    key: String,
 }
 
-impl<'a, 'de> ColonSeparated<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>) -> Self {
+impl<'a, R: 'a> ColonSeparated<'a, R> {
+    fn new(de: &'a mut Deserializer<R>) -> Self {
         ColonSeparated {
-            de
+            de,
+            index: 0,
+            key: String::new(),
+        }
+    }
+}
+
+impl<'de, 'a, R: Read<'de> + 'a> ColonSeparated<'a, R> {
+    // Count one more entry against `Limits::max_entries`.
+    fn next_entry(&mut self) -> Result<usize> {
+        if self.index == self.de.config.limits.max_entries {
+            return Err(self.de.error(ErrorKind::TooManyEntries));
         }
+        self.index += 1;
+        Ok(self.index - 1)
     }
 }
 
-impl<'de, 'a> MapAccess<'de> for ColonSeparated<'a, 'de> {
+impl<'de, 'a, R: Read<'de> + 'a> MapAccess<'de> for ColonSeparated<'a, R> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
@@ -130,39 +541,42 @@ impl<'de, 'a> MapAccess<'de> for ColonSeparated<'a, 'de> {
         K: DeserializeSeed<'de>,
     {
         // Check if there are no more entries.
-        if self.de.peek_char()? == 'e' {
-            return Ok(None);
+        let start = self.de.read.byte_offset();
+        match self.de.peek_byte()? {
+            b'e' => return Ok(None),
+            b'0'..=b'9' => {}
+            _ => return Err(self.de.error(ErrorKind::KeyMustBeAString)),
         }
+        self.next_entry()?;
 
-        // Colon is required before every entry except the first.
-        // if !self.first && self.de.next_char()? != ':' {
-        //     return Err(Error::ExpectedMapColon);
-        // }
-
-        // self.first = false;
+        // Keys are always byte strings, so parse the key here and remember it
+        // before handing it to the key's `Deserialize` impl.
+        let key = self.de.parse_string()?;
+        self.key.clear();
+        self.key.push_str(&String::from_utf8_lossy(&key));
 
-        // Deserialize a map key.
-        let result = seed.deserialize(&mut *self.de).map(Some);
-        return result;
+        match seed.deserialize(MapKey { key }) {
+            Ok(key) => Ok(Some(key)),
+            Err(err) => Err(err.fix_offset(start).prepend_key(&self.key)),
+        }
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
     where
         V: DeserializeSeed<'de>,
     {
-        // let ch = self.de.next_char()?; 
-        // if ch != ':' {
-        //     println!("Expected ':', found '{}'", ch);
-        //     return Err(Error::ExpectedMapColon);
-        // }
         // Deserialize a map value.
-        seed.deserialize(&mut *self.de)
+        let start = self.de.read.byte_offset();
+        match seed.deserialize(&mut *self.de) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(err.fix_offset(start).prepend_key(&self.key)),
+        }
     }
 }
 
 // `SeqAccess` is provided to the `Visitor` to give it the ability to iterate
 // through elements of the sequence.
-impl<'de, 'a> SeqAccess<'de> for ColonSeparated<'a, 'de> {
+impl<'de, 'a, R: Read<'de> + 'a> SeqAccess<'de> for ColonSeparated<'a, R> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -170,16 +584,171 @@ impl<'de, 'a> SeqAccess<'de> for ColonSeparated<'a, 'de> {
         T: DeserializeSeed<'de>,
     {
         // Check if there are no more elements.
-        if self.de.peek_char()? == 'e' {
+        if self.de.peek_byte()? == b'e' {
             return Ok(None);
         }
 
         // Deserialize an array element.
-        seed.deserialize(&mut *self.de).map(Some)
+        let start = self.de.read.byte_offset();
+        let index = self.next_entry()?;
+        match seed.deserialize(&mut *self.de) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => Err(err.fix_offset(start).prepend_index(index)),
+        }
+    }
+}
+
+// Deserializer for a dictionary key that has already been parsed. Keys are
+// byte strings, only required to be valid UTF-8 when a `str` is asked for.
+struct MapKey<'de, 's> {
+    key: Reference<'de, 's, [u8]>,
+}
+
+impl<'de, 's> MapKey<'de, 's> {
+    fn into_str(self) -> Result<Reference<'de, 's, str>> {
+        match self.key {
+            Reference::Borrowed(bytes) => str::from_utf8(bytes).map(Reference::Borrowed),
+            Reference::Copied(bytes) => str::from_utf8(bytes).map(Reference::Copied),
+        }
+        .map_err(|_| ErrorKind::InvalidUtf8.into())
+    }
+}
+
+impl<'de, 's> de::Deserializer<'de> for MapKey<'de, 's> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visit_bytes_or_str(self.key, visitor)
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.into_str()? {
+            Reference::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Reference::Copied(s) => visitor.visit_str(s),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.key {
+            Reference::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Reference::Copied(bytes) => visitor.visit_bytes(bytes),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str,
+                                     visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
     }
+
+    fn deserialize_enum<V>(self, _name: &'static str,
+                           _variants: &'static [&'static str],
+                           visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_enum((&*self.into_str()?).into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct Enum<'a, R: 'a> {
+    de: &'a mut Deserializer<R>,
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'a, R: 'a> Enum<'a, R> {
+    fn new(de: &'a mut Deserializer<R>) -> Self {
+        Enum { de }
+    }
+}
+
+// `EnumAccess` is provided to the `Visitor` to give it the ability to determine
+// which variant of the enum is supposed to be deserialized.
+impl<'de, 'a, R: Read<'de> + 'a> EnumAccess<'de> for Enum<'a, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        // The dictionary key holds the variant name.
+        let val = seed.deserialize(&mut *self.de)?;
+        Ok((val, self))
+    }
+}
+
+// `VariantAccess` is provided to the `Visitor` to give it the ability to see
+// the content of the single variant that it decided to deserialize.
+impl<'de, 'a, R: Read<'de> + 'a> VariantAccess<'de> for Enum<'a, R> {
+    type Error = Error;
+
+    // Unit variants are always encoded as bare byte strings, which are
+    // handled in `deserialize_enum` before an `Enum` is ever created.
+    fn unit_variant(self) -> Result<()> {
+        Err(de::Error::invalid_type(Unexpected::Map, &"unit variant"))
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_seq(self.de, visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str],
+                         visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_map(self.de, visitor)
+    }
+}
+
+impl<'de, R: Read<'de>> de::Deserializer<'de> for &mut Deserializer<R> {
     type Error = Error;
 
     // Look at the input data to decide what Serde data model type to
@@ -189,55 +758,79 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        match self.peek_char()? {
-            'd' => self.deserialize_map(visitor),
-            '0'..='9' => self.deserialize_str(visitor),
-            _ => Err(Error::Syntax),
+        let start = self.read.byte_offset();
+        match self.peek_byte()? {
+            // Non-negative integers are visited as unsigned, like every other
+            // self-describing serde format.
+            b'i' => match self.parse_integer()? {
+                (false, int) => match u64::try_from(int) {
+                    Ok(int) => visitor.visit_u64(int),
+                    Err(_) => visitor.visit_u128(int),
+                },
+                (true, int) if int <= i64::MIN.unsigned_abs() as u128 => {
+                    visitor.visit_i64((int as i64).wrapping_neg())
+                }
+                (true, int) if int <= i128::MIN.unsigned_abs() => {
+                    visitor.visit_i128((int as i128).wrapping_neg())
+                }
+                (true, _) => Err(Error::at(ErrorKind::IntegerOverflow, start)),
+            },
+            b'l' => self.deserialize_seq(visitor),
+            b'd' => self.deserialize_map(visitor),
+            b'0'..=b'9' => visit_bytes_or_str(self.parse_string()?, visitor),
+            _ => Err(self.error(ErrorKind::Syntax)),
         }
     }
 
     // Much like `deserialize_seq` but calls the visitors `visit_map` method
     // with a `MapAccess` implementation, rather than the visitor's `visit_seq`
     // method with a `SeqAccess` implementation.
-    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value>
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
         // Parse the opening brace of the map.
-        if self.next_char()? == 'd' {
-            // Visitor
-            let value = visitor.visit_map(ColonSeparated::new(&mut self))?;
-            // Parse the closing brace of the map.
-            if self.next_char()? == 'e' {
-                return Ok(value);
-            } else {
-                return Err(Error::ExpectedMapEnd);
-            } 
-        } else {
-            return Err(Error::ExpectedMap);
-        }
-        // unimplemented!()
+        let start = self.read.byte_offset();
+        self.expect_byte(b'd', ErrorKind::ExpectedMap)?;
+        self.enter_container(start)?;
+        // Visitor
+        let value = visitor.visit_map(ColonSeparated::new(self));
+        self.leave_container();
+        let value = value?;
+        // Parse the closing brace of the map.
+        self.expect_byte(b'e', ErrorKind::ExpectedMapEnd)?;
+        Ok(value)
     }
 
-    fn deserialize_bool<V>(self, _visitor: V) -> Result<V::Value>
+    // Booleans are the integers 0 and 1, unless disabled in the `Config`.
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        return Err(Error::BoolUnsupported);
+        if !self.config.bools_as_integers {
+            return Err(self.error(ErrorKind::BoolUnsupported));
+        }
+
+        let start = self.read.byte_offset();
+        match self.parse_integer()? {
+            (_, 0) => visitor.visit_bool(false),
+            (false, 1) => visitor.visit_bool(true),
+            _ => Err(Error::at(ErrorKind::InvalidBool, start)),
+        }
     }
 
-    fn deserialize_i8<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_i8(self.parse_signed()?)
     }
 
-    fn deserialize_i16<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_i16(self.parse_signed()?)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
@@ -247,68 +840,94 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_i32(self.parse_signed()?)
     }
 
-    fn deserialize_i64<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+        where
+            V: Visitor<'de>,
+    {
+        visitor.visit_i64(self.parse_signed()?)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+        where
+            V: Visitor<'de>,
+    {
+        visitor.visit_u8(self.parse_unsigned()?)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_u16(self.parse_unsigned()?)
     }
 
-    fn deserialize_u8<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_u32(self.parse_unsigned()?)
     }
 
-    fn deserialize_u16<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_u64(self.parse_unsigned()?)
     }
 
-    fn deserialize_u32<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_i128(self.parse_signed()?)
     }
 
-    fn deserialize_u64<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_u128(self.parse_unsigned()?)
     }
 
     fn deserialize_f32<V>(self, _visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        Err(self.error(ErrorKind::FloatUnsupported))
     }
 
     fn deserialize_f64<V>(self, _visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        Err(self.error(ErrorKind::FloatUnsupported))
     }
 
-    fn deserialize_char<V>(self, _visitor: V) -> Result<V::Value>
+    // A `char` is a byte string holding exactly one UTF-8 encoded character.
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        let start = self.read.byte_offset();
+        let s = self.parse_str()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => visitor.visit_char(ch),
+            _ => {
+                let err: Error = de::Error::invalid_value(Unexpected::Str(&s), &"a single character");
+                Err(err.fix_offset(start))
+            }
+        }
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        visitor.visit_borrowed_str(self.parse_string()?)
-        // unimplemented!()
+        match self.parse_str()? {
+            Reference::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Reference::Copied(s) => visitor.visit_str(s),
+        }
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
@@ -316,85 +935,136 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             V: Visitor<'de>,
     {
         self.deserialize_str(visitor)
-        // unimplemented!()
     }
 
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value>
+    // Byte strings are borrowed straight out of the input when it is a
+    // slice, so `&'de [u8]` and a borrowed `Cow<'de, [u8]>` work without
+    // copying. From a stream they can only be lent out of the scratch buffer.
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        match self.parse_string()? {
+            Reference::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Reference::Copied(bytes) => visitor.visit_bytes(bytes),
+        }
     }
 
-    fn deserialize_byte_buf<V>(self, _visitor: V) -> Result<V::Value>
+    // The visitor wants ownership, but borrowing is still the cheapest way to
+    // hand over a slice; only bytes copied from a stream are handed over as a
+    // `Vec` right away.
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        match self.parse_string()? {
+            Reference::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Reference::Copied(bytes) => visitor.visit_byte_buf(bytes.to_vec()),
+        }
     }
 
-    fn deserialize_option<V>(self, _visitor: V) -> Result<V::Value>
+    // Bencode has no null: `None` is represented by leaving the dictionary key
+    // out altogether, which serde already maps to `None` for optional fields.
+    // Any value that is actually present is therefore `Some`.
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_some(self)
     }
 
-    fn deserialize_unit<V>(self, _visitor: V) -> Result<V::Value>
+    // Bencode has no null, so `()` is written as an empty list.
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        self.expect_byte(b'l', ErrorKind::ExpectedList)?;
+        self.expect_byte(b'e', ErrorKind::ExpectedListEnd)?;
+        visitor.visit_unit()
     }
 
+    // Unit structs are represented the same way as `()`.
     fn deserialize_unit_struct<V>(self, _name: &'static str, 
-                                  _visitor: V) -> Result<V::Value>
+                                  visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        self.deserialize_unit(visitor)
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str,
-                                     _visitor: V) -> Result<V::Value>
+    // As is done here, serializers are encouraged to treat newtype structs as
+    // insignificant wrappers around the data they contain. That means not
+    // parsing anything other than the contained value. `RawValue` instead
+    // gets the bytes of the value itself.
+    fn deserialize_newtype_struct<V>(self, name: &'static str,
+                                     visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        if name == RAW_VALUE_TOKEN {
+            return self.deserialize_raw_value(visitor);
+        }
+        visitor.visit_newtype_struct(self)
     }
 
-    fn deserialize_seq<V>(mut self, visitor: V) -> Result<V::Value>
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
         // Parse the opening bracket of the sequence.
-        if self.next_char()? == 'l' {
-            // Give the visitor access to each element of the sequence.
-            let value = visitor.visit_seq(ColonSeparated::new(&mut self))?;
-            // Parse the closing bracket of the sequence.
-            if self.next_char()? == 'e' {
-                Ok(value)
-            } else {
-                Err(Error::ExpectedListEnd)
-            }
-        } else {
-            Err(Error::ExpectedList)
-        }
+        let start = self.read.byte_offset();
+        self.expect_byte(b'l', ErrorKind::ExpectedList)?;
+        self.enter_container(start)?;
+        // Give the visitor access to each element of the sequence.
+        let value = visitor.visit_seq(ColonSeparated::new(self));
+        self.leave_container();
+        let value = value?;
+        // Parse the closing bracket of the sequence.
+        self.expect_byte(b'e', ErrorKind::ExpectedListEnd)?;
+        Ok(value)
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+    // Tuples, tuple structs and fixed-size arrays are plain lists. Too few
+    // elements are reported by the visitor itself; a visitor for a fixed-size
+    // type stops asking once it has `len` elements, so whatever it leaves
+    // behind is counted here to report the actual length of the list.
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        let start = self.read.byte_offset();
+        self.expect_byte(b'l', ErrorKind::ExpectedList)?;
+        self.enter_container(start)?;
+
+        // The leftovers are still inside the list, so they are skipped before
+        // leaving it and count against the same limits as the rest.
+        let mut seq = ColonSeparated::new(self);
+        let value = visitor.visit_seq(&mut seq).and_then(|value| {
+            let visited = seq.index;
+            while seq.de.peek_byte()? != b'e' {
+                seq.next_entry()?;
+                seq.de.ignore_value()?;
+            }
+            Ok((value, seq.index - visited))
+        });
+        self.leave_container();
+        let (value, extra) = value?;
+        self.read.discard();
+
+        if extra == 0 {
+            Ok(value)
+        } else {
+            let err: Error = de::Error::invalid_length(len + extra, &&*format!("a list of {} elements", len));
+            Err(err.fix_offset(start))
+        }
     }
 
     fn deserialize_tuple_struct<V>(self, _name: &'static str,
-                                   _len: usize, _visitor: V) -> Result<V::Value>
+                                   len: usize, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        self.deserialize_tuple(len, visitor)
     }
 
     fn deserialize_struct<V>(self, _name: &'static str,
@@ -406,24 +1076,41 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_map(visitor)
     }
 
+    // Enums are externally tagged: a unit variant is a bare byte string
+    // holding its name, any other variant is a single-entry dictionary.
     fn deserialize_enum<V>(self, _name: &'static str,
                            _variants: &'static [&'static str],
-                           _visitor: V) -> Result<V::Value>
+                           visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        unimplemented!()
+        match self.peek_byte()? {
+            b'0'..=b'9' => visitor.visit_enum((&*self.parse_str()?).into_deserializer()),
+            b'd' => {
+                let start = self.read.byte_offset();
+                self.read.discard();
+                self.enter_container(start)?;
+                let value = visitor.visit_enum(Enum::new(self));
+                self.leave_container();
+                let value = value?;
+                self.expect_byte(b'e', ErrorKind::ExpectedMapEnd)?;
+                Ok(value)
+            }
+            _ => Err(self.error(ErrorKind::ExpectedEnum)),
+        }
     }
 
     // An identifier in Serde is the type that identifies a field of a struct or
     // the variant of an enum. In JSON, struct fields and enum variants are
     // represented as strings. In other formats they may be represented as
     // numeric indices.
+    // Field and variant names are matched as bytes when they aren't UTF-8,
+    // so an unknown binary key is ignored rather than rejected.
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        self.deserialize_str(visitor)
+        visit_bytes_or_str(self.parse_string()?, visitor)
     }
 
     // Like `deserialize_any` but indicates to the `Deserializer` that it makes
@@ -441,7 +1128,87 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         where
             V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.ignore_value()?;
+        visitor.visit_unit()
+    }
+}
+
+// Iterator that deserializes a stream of back-to-back bencoded values, such as
+// a capture of tracker responses or DHT packets. Bencode needs no separator
+// between values: each one ends exactly where the next begins.
+//
+//     let stream = Deserializer::from_slice(input).into_iter::<Value>();
+//     for value in stream {
+//         println!("{:?}", value?);
+//     }
+//
+// Iteration stops after the first error, since there is no way to know where
+// the next value would start.
+pub struct StreamDeserializer<'de, R, T> {
+    de: Deserializer<R>,
+    offset: usize,
+    failed: bool,
+    output: PhantomData<T>,
+    lifetime: PhantomData<&'de ()>,
+}
+
+impl<'de, R, T> StreamDeserializer<'de, R, T>
+where
+    R: Read<'de>,
+    T: Deserialize<'de>,
+{
+    // Create a bencode stream deserializer from one of the possible
+    // serde_bencode input sources. Typically it is more convenient to use
+    // `Deserializer::from_slice(...).into_iter()` and friends.
+    pub fn new(read: R) -> Self {
+        Deserializer::new(read).into_iter()
+    }
+
+    // Number of bytes consumed by the values deserialized so far. Before a
+    // call to `next` this is where the next value starts; after a successful
+    // call it is where the value just returned ended.
+    //
+    // After an error this still points at the start of the value that could
+    // not be deserialized.
+    pub fn byte_offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'de, R, T> Iterator for StreamDeserializer<'de, R, T>
+where
+    R: Read<'de>,
+    T: Deserialize<'de>,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if self.failed {
+            return None;
+        }
+
+        match self.de.read.peek() {
+            Ok(None) => return None,
+            Ok(Some(_)) => {}
+            Err(err) => {
+                self.failed = true;
+                return Some(Err(err));
+            }
+        }
+
+        // Limits apply to each value on its own.
+        self.de.value_start = self.offset;
+        self.de.allocated = 0;
+        match T::deserialize(&mut self.de) {
+            Ok(value) => {
+                self.offset = self.de.read.byte_offset();
+                Some(Ok(value))
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err.fix_offset(self.offset)))
+            }
+        }
     }
 }
 
@@ -449,16 +1216,98 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
 // `from_xyz` methods such as `from_str`, `from_bytes`, or `from_reader`
 // depending on what Rust types the deserializer is able to consume as input.
 //
-// This basic deserializer supports only `from_str`.
+// Bencode is a binary format, so `from_bytes` is the primary entry point and
+// `from_str` is a convenience for inputs that happen to be valid UTF-8.
+fn from_trait<'de, R, T>(read: R) -> Result<T>
+where
+    R: Read<'de>,
+    T: Deserialize<'de>,
+{
+    let mut deserializer = Deserializer::new(read);
+    let t = T::deserialize(&mut deserializer).map_err(|err| err.fix_offset(0))?;
+    deserializer.end()?;
+    Ok(t)
+}
+
+pub fn from_bytes<'a, T>(b: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_trait(SliceRead::new(b))
+}
+
 pub fn from_str<'a, T>(s: &'a str) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer::from_str(s);
-    let t = T::deserialize(&mut deserializer)?;
-    if deserializer.input.is_empty() {
-        Ok(t)
-    } else {
-        Err(Error::TrailingCharacters)
-    }
-}
\ No newline at end of file
+    from_bytes(s.as_bytes())
+}
+
+// Deserialize an instance of type `T` from an IO stream of bencode. Nothing
+// can be borrowed from a stream, so `T` has to own all of its data.
+//
+// The whole stream must be a single value; bytes are read one at a time, so
+// wrap unbuffered readers (files, sockets) in an `io::BufReader`.
+pub fn from_reader<R, T>(rdr: R) -> Result<T>
+where
+    R: io::Read,
+    T: DeserializeOwned,
+{
+    from_trait(IoRead::new(rdr))
+}
+
+// Read a `bool` encoded as `i0e` or `i1e` wherever serde hands the value to a
+// generic deserializer rather than this one, which is the case inside
+// `#[serde(flatten)]`, untagged and internally tagged enums. Those buffer the
+// input first and only accept a real boolean for a `bool` field, so use this
+// for such fields instead:
+//
+//     #[serde(deserialize_with = "serde_bencode::de::bool_from_integer")]
+//     ro: bool,
+//
+// Unlike a plain `bool` this ignores `Config::bools_as_integers`.
+pub fn bool_from_integer<'de, D>(deserializer: D) -> std::result::Result<bool, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    struct BoolVisitor;
+
+    impl<'de> Visitor<'de> for BoolVisitor {
+        type Value = bool;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a boolean encoded as i0e or i1e")
+        }
+
+        fn visit_bool<E>(self, v: bool) -> std::result::Result<bool, E>
+        where
+            E: de::Error,
+        {
+            Ok(v)
+        }
+
+        fn visit_i64<E>(self, v: i64) -> std::result::Result<bool, E>
+        where
+            E: de::Error,
+        {
+            match v {
+                0 => Ok(false),
+                1 => Ok(true),
+                _ => Err(de::Error::invalid_value(Unexpected::Signed(v), &self)),
+            }
+        }
+
+        fn visit_u64<E>(self, v: u64) -> std::result::Result<bool, E>
+        where
+            E: de::Error,
+        {
+            match v {
+                0 => Ok(false),
+                1 => Ok(true),
+                _ => Err(de::Error::invalid_value(Unexpected::Unsigned(v), &self)),
+            }
+        }
+    }
+
+    deserializer.deserialize_any(BoolVisitor)
+}
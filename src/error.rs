@@ -1,13 +1,34 @@
 use std;
 use std::fmt::{self, Display};
+use std::io;
 
 use serde::{ser, de};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum Error {
+// This type represents all possible errors that can occur when serializing or
+// deserializing bencode. Besides what went wrong (`kind`), errors raised while
+// deserializing remember where: the byte offset into the input and the path
+// of dictionary keys and list indices leading to the offending value, e.g.
+// `info.files[17].path[2]`.
+//
+// Boxed so that `Result<T>` stays small on the happy path.
+pub struct Error {
+    err: Box<ErrorImpl>,
+}
+
+struct ErrorImpl {
+    kind: ErrorKind,
+    offset: Option<usize>,
+    // Built up from the innermost segment outwards as the error
+    // bubbles up, so it is empty until the error leaves its first container.
+    path: String,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
     Message(String),
+    Io(io::Error),
     Syntax,
     Eof,
     ExpectedColon,
@@ -18,36 +39,210 @@ pub enum Error {
     ExpectedMapEnd,
     ExpectedList,
     ExpectedListEnd,
+    ExpectedEnum,
     UnexpectedChar,
     TrailingCharacters,
+    InvalidUtf8,
+    KeyMustBeAString,
+    DuplicateKey,
+    UnexpectedNone,
+    InvalidBool,
+    IntegerOverflow,
+    LeadingZero,
+    LengthOverflow,
+    NegativeZero,
+    RecursionLimitExceeded,
+    StringTooLong,
+    TooManyEntries,
+    AllocationLimitExceeded,
+    InputTooLarge,
     /* Unsupported errors */
-    BoolUnsupported
+    BoolUnsupported,
+    FloatUnsupported
+}
+
+impl Error {
+    // What went wrong.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.err.kind
+    }
+
+    // Byte offset into the input at which deserialization failed. Syntax
+    // errors point at the first byte that could not be parsed, other errors
+    // at the start of the value that could not be deserialized. Always `None`
+    // for errors raised while serializing.
+    pub fn offset(&self) -> Option<usize> {
+        self.err.offset
+    }
+
+    // Dictionary keys and list indices leading from the top-level value to
+    // the one that failed, like `info.files[17].path[2]`. `None` when the
+    // top-level value itself failed.
+    pub fn path(&self) -> Option<&str> {
+        if self.err.path.is_empty() {
+            None
+        } else {
+            Some(&self.err.path)
+        }
+    }
+
+    pub fn into_kind(self) -> ErrorKind {
+        self.err.kind
+    }
+
+    pub(crate) fn at(kind: ErrorKind, offset: usize) -> Self {
+        let mut err = Error::from(kind);
+        err.err.offset = Some(offset);
+        err
+    }
+
+    // Errors raised by visitors know nothing about the input, so they are
+    // given the offset of the value being deserialized on their way out. The
+    // innermost offset wins.
+    pub(crate) fn fix_offset(mut self, offset: usize) -> Self {
+        if self.err.offset.is_none() {
+            self.err.offset = Some(offset);
+        }
+        self
+    }
+
+    pub(crate) fn prepend_key(mut self, key: &str) -> Self {
+        self.prepend_path(key);
+        self
+    }
+
+    pub(crate) fn prepend_index(mut self, index: usize) -> Self {
+        self.prepend_path(&format!("[{}]", index));
+        self
+    }
+
+    // Keys are joined to what follows with a dot, indices need no separator.
+    fn prepend_path(&mut self, segment: &str) {
+        let path = &mut self.err.path;
+        if !path.is_empty() && !path.starts_with('[') {
+            path.insert(0, '.');
+        }
+        path.insert_str(0, segment);
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            err: Box::new(ErrorImpl {
+                kind,
+                offset: None,
+                path: String::new(),
+            }),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::from(ErrorKind::Io(err))
+    }
 }
 
 impl ser::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
-        Error::Message(msg.to_string())
+        Error::from(ErrorKind::Message(msg.to_string()))
     }
 }
 
 impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
-        Error::Message(msg.to_string())
+        Error::from(ErrorKind::Message(msg.to_string()))
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Message(ref msg) => formatter.write_str(msg),
+            ErrorKind::Io(ref err) => Display::fmt(err, formatter),
+            ErrorKind::Syntax => formatter.write_str("expected a bencode value"),
+            ErrorKind::Eof => formatter.write_str("unexpected end of input"),
+            ErrorKind::ExpectedColon => formatter.write_str("expected `:` after byte string length"),
+            ErrorKind::ExpectedI => formatter.write_str("expected `i` to start an integer"),
+            ErrorKind::ExpectedInteger => formatter.write_str("expected a digit"),
+            ErrorKind::ExpectedMap => formatter.write_str("expected `d` to start a dictionary"),
+            ErrorKind::ExpectedMapColon => formatter.write_str("expected `:` between dictionary key and value"),
+            ErrorKind::ExpectedMapEnd => formatter.write_str("expected `e` to end a dictionary"),
+            ErrorKind::ExpectedList => formatter.write_str("expected `l` to start a list"),
+            ErrorKind::ExpectedListEnd => formatter.write_str("expected `e` to end a list"),
+            ErrorKind::ExpectedEnum => formatter.write_str("expected a byte string or a dictionary for an enum"),
+            ErrorKind::UnexpectedChar => formatter.write_str("expected a digit or `e` in integer"),
+            ErrorKind::TrailingCharacters => formatter.write_str("trailing bytes after the value"),
+            ErrorKind::InvalidUtf8 => formatter.write_str("byte string is not valid UTF-8"),
+            ErrorKind::IntegerOverflow => formatter.write_str("integer out of range for the target type"),
+            ErrorKind::LengthOverflow => formatter.write_str("byte string length does not fit in usize"),
+            ErrorKind::LeadingZero => formatter.write_str("integer has a leading zero"),
+            ErrorKind::NegativeZero => formatter.write_str("negative zero is not a valid integer"),
+            ErrorKind::RecursionLimitExceeded => formatter.write_str("lists and dictionaries are nested too deeply"),
+            ErrorKind::StringTooLong => formatter.write_str("byte string is longer than the configured limit"),
+            ErrorKind::TooManyEntries => formatter.write_str("list or dictionary has more entries than the configured limit"),
+            ErrorKind::AllocationLimitExceeded => formatter.write_str("byte strings exceed the configured allocation limit"),
+            ErrorKind::InputTooLarge => formatter.write_str("input is larger than the configured limit"),
+            ErrorKind::KeyMustBeAString => formatter.write_str("dictionary key must be a byte string"),
+            ErrorKind::DuplicateKey => formatter.write_str("dictionary has a duplicate key"),
+            ErrorKind::InvalidBool => formatter.write_str("boolean must be encoded as i0e or i1e"),
+            ErrorKind::BoolUnsupported => formatter.write_str("booleans are disabled in the config"),
+            ErrorKind::FloatUnsupported => formatter.write_str("bencode has no floating point numbers"),
+            ErrorKind::UnexpectedNone => formatter.write_str("None can only be serialized as a dictionary value, where it is omitted"),
+        }
     }
 }
 
+// Reads like "integer has a leading zero at byte 1234 (info.files[17].length)".
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str(std::error::Error::description(self))
+        Display::fmt(&self.err.kind, formatter)?;
+        if let Some(offset) = self.err.offset {
+            write!(formatter, " at byte {}", offset)?;
+        }
+        if !self.err.path.is_empty() {
+            write!(formatter, " ({})", self.err.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_struct("Error")
+            .field("kind", &self.err.kind)
+            .field("offset", &self.err.offset)
+            .field("path", &self.err.path)
+            .finish()
     }
 }
 
 impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        match *self {
-            Error::Eof => "unexpected end of input",
-            /* and so forth */
-            _ => "Unimplemented message",
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.err.kind {
+            ErrorKind::Io(ref err) => Some(err),
+            _ => None,
         }
     }
-}
\ No newline at end of file
+}
+
+// `io::Error` can't be compared, so two I/O errors are considered equal when
+// their kinds match.
+impl PartialEq for ErrorKind {
+    fn eq(&self, other: &ErrorKind) -> bool {
+        match (self, other) {
+            (ErrorKind::Message(a), ErrorKind::Message(b)) => a == b,
+            (ErrorKind::Io(a), ErrorKind::Io(b)) => a.kind() == b.kind(),
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl PartialEq for Error {
+    fn eq(&self, other: &Error) -> bool {
+        self.err.kind == other.err.kind
+            && self.err.offset == other.err.offset
+            && self.err.path == other.err.path
+    }
+}
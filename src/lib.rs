@@ -1,6 +1,15 @@
+pub mod config;
 pub mod de;
 pub mod error;
-mod ser;
+mod read;
+pub mod ser;
+pub mod value;
+
+pub use crate::config::{Config, Limits};
+pub use crate::de::{from_bytes, from_reader, from_str, Deserializer, StreamDeserializer};
+pub use crate::error::{Error, ErrorKind, Result};
+pub use crate::ser::{to_bytes, to_string, to_writer, Serializer};
+pub use crate::value::{from_value, to_value, RawValue, Value};
 
 #[cfg(test)]
 mod tests {
@@ -35,7 +44,7 @@ mod tests {
     {
         use crate::ser;
         let example: String = "a".to_string();
-        let example_ser: String = ser::to_str(&example).unwrap();
+        let example_ser: String = ser::to_string(&example).unwrap();
         println!("Serialized String: {}", example_ser);
         assert!(example_ser == "1:a")
     }
@@ -45,7 +54,7 @@ mod tests {
     {
         use crate::ser;
         let example: i32 = 10;
-        let example_ser: String = ser::to_str(&example).unwrap();
+        let example_ser: String = ser::to_string(&example).unwrap();
         println!("Serialized Integer: {}", example_ser);
         assert!(example_ser == "i10e")
     }
@@ -55,7 +64,7 @@ mod tests {
     {
         use crate::ser;
         let example: Vec<String> = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let example_ser: String = ser::to_str(&example).unwrap();
+        let example_ser: String = ser::to_string(&example).unwrap();
         println!("Serialized Vector: {}", example_ser);
         assert!(example_ser == "l1:a1:b1:ce")
     }
@@ -67,7 +76,7 @@ mod tests {
         let example: TestStruct = TestStruct {
             a: "hello".to_string()
         };
-        let example_ser: String = ser::to_str(&example).unwrap();
+        let example_ser: String = ser::to_string(&example).unwrap();
         println!("Serialized Str Map: {}", example_ser);
         assert!(example_ser == "d1:a5:helloe")
     }
@@ -79,7 +88,7 @@ mod tests {
         let example: TestStructInt = TestStructInt {
             a: 10
         };
-        let example_ser: String = ser::to_str(&example).unwrap();
+        let example_ser: String = ser::to_string(&example).unwrap();
         println!("Serialized Int Map: {}", example_ser);
         assert!(example_ser == "d1:ai10ee")
     }
@@ -91,7 +100,7 @@ mod tests {
         let example: TestStructVec = TestStructVec {
             a: vec!["a".to_string(), "b".to_string(), "c".to_string()]
         };
-        let example_ser: String = ser::to_str(&example).unwrap();
+        let example_ser: String = ser::to_string(&example).unwrap();
         println!("Serialized Vec Map: {}", example_ser);
         assert!(example_ser == "d1:al1:a1:b1:cee")
     }
@@ -106,7 +115,7 @@ mod tests {
                 a: "world".to_string()
             }
         };
-        let example_ser: String = ser::to_str(&example).unwrap();
+        let example_ser: String = ser::to_string(&example).unwrap();
         println!("Serialized Map Map: {}", example_ser);
         assert!(example_ser == "d1:a5:hello1:bd1:a5:worldee")
     }
@@ -184,4 +193,1557 @@ mod tests {
         assert!(example_de.a == "hello");
         assert!(example_de.b.a == "world");
     }
+
+    #[test]
+    fn de_bytes_map()
+    {
+        use crate::de;
+        let example: &[u8] = b"d1:a5:hello1:bd1:a5:worldee";
+        let example_de: TestStructMap = de::from_bytes(example).unwrap();
+        assert!(example_de.a == "hello");
+        assert!(example_de.b.a == "world");
+    }
+
+    #[test]
+    fn de_bytes_borrowed_str()
+    {
+        use crate::de;
+        let example: &[u8] = b"5:hello";
+        let example_de: &str = de::from_bytes(example).unwrap();
+        assert!(example_de == "hello")
+    }
+
+    #[test]
+    fn de_bytes_invalid_utf8()
+    {
+        use crate::de;
+        use crate::error::{Error, ErrorKind};
+        let example: &[u8] = b"2:\xff\xfe";
+        let example_de: Result<String, Error> = de::from_bytes(example);
+        assert!(example_de.unwrap_err().kind() == &ErrorKind::InvalidUtf8)
+    }
+
+    #[test]
+    fn ser_bytes()
+    {
+        use crate::ser;
+        let example = serde_bytes::Bytes::new(b"\x00\xff\x13");
+        let example_ser: Vec<u8> = ser::to_bytes(&example).unwrap();
+        assert!(example_ser == b"3:\x00\xff\x13")
+    }
+
+    #[test]
+    fn ser_bytes_in_map()
+    {
+        use crate::ser;
+        #[derive(Serialize)]
+        struct Peers<'a>
+        {
+            #[serde(with = "serde_bytes")]
+            peers: &'a [u8],
+        }
+        let example = Peers {
+            peers: b"\x7f\x00\x00\x01\x1a\xe1"
+        };
+        let example_ser: Vec<u8> = ser::to_vec(&example).unwrap();
+        assert!(example_ser == b"d5:peers6:\x7f\x00\x00\x01\x1a\xe1e")
+    }
+
+    #[test]
+    fn ser_str_invalid_utf8()
+    {
+        use crate::ser;
+        use crate::error::ErrorKind;
+        let example = serde_bytes::Bytes::new(b"\xff");
+        assert!(ser::to_string(&example).unwrap_err().kind() == &ErrorKind::InvalidUtf8)
+    }
+
+    #[test]
+    fn reexports_round_trip()
+    {
+        let example = TestStructMap {
+            a: "hello".to_string(),
+            b: TestStruct {
+                a: "world".to_string()
+            }
+        };
+        let mut serializer = crate::Serializer::new();
+        example.serialize(&mut serializer).unwrap();
+        let example_ser = serializer.into_inner();
+        assert!(example_ser == crate::to_bytes(&example).unwrap());
+        let mut deserializer = crate::Deserializer::from_slice(&example_ser);
+        let example_de = TestStructMap::deserialize(&mut deserializer).unwrap();
+        assert!(example_de == crate::from_bytes(&example_ser).unwrap());
+        assert!(example_de == example)
+    }
+
+    #[test]
+    fn value_round_trip()
+    {
+        use crate::value::{self, Value};
+        use std::collections::BTreeMap;
+        let example = TestStructVec {
+            a: vec!["a".to_string(), "b".to_string(), "c".to_string()]
+        };
+        let example_value = value::to_value(&example).unwrap();
+        let mut expected = BTreeMap::new();
+        expected.insert(b"a".to_vec(), Value::List(vec![
+            Value::Bytes(b"a".to_vec()),
+            Value::Bytes(b"b".to_vec()),
+            Value::Bytes(b"c".to_vec()),
+        ]));
+        assert!(example_value == Value::Dict(expected));
+        let example_de: TestStructVec = value::from_value(example_value).unwrap();
+        assert!(example_de == example)
+    }
+
+    #[test]
+    fn value_ser()
+    {
+        use crate::{ser, value::Value};
+        use std::collections::BTreeMap;
+        let mut dict = BTreeMap::new();
+        dict.insert(b"b".to_vec(), Value::Int(-3));
+        dict.insert(b"a".to_vec(), Value::List(vec![Value::Bytes(vec![0xff])]));
+        let example_ser = ser::to_bytes(&Value::Dict(dict)).unwrap();
+        assert!(example_ser == b"d1:al1:\xffe1:bi-3ee")
+    }
+
+    #[test]
+    fn value_de()
+    {
+        use crate::{de, value::Value};
+        let example: Value = de::from_str("d1:a5:hello1:bd1:a5:worldee").unwrap();
+        let b = &example.as_dict().unwrap()[&b"b".to_vec()];
+        assert!(example.as_dict().unwrap()[&b"a".to_vec()].as_str() == Some("hello"));
+        assert!(b.as_dict().unwrap()[&b"a".to_vec()].as_str() == Some("world"))
+    }
+
+    #[test]
+    fn value_char_and_float()
+    {
+        use crate::{value, ErrorKind};
+        assert!(value::to_value('é').unwrap() == value::Value::Bytes("é".as_bytes().to_vec()));
+        let err = value::to_value(1.5f64).unwrap_err();
+        assert!(err.kind() == &ErrorKind::FloatUnsupported);
+        let err = value::to_value(1.5f32).unwrap_err();
+        assert!(err.kind() == &ErrorKind::FloatUnsupported)
+    }
+
+    #[test]
+    fn de_any_value()
+    {
+        use crate::{de, value::Value};
+        let example: Value = de::from_bytes(b"d1:ai-42e1:bl1:\xffi7eee").unwrap();
+        let dict = example.as_dict().unwrap();
+        assert!(dict[&b"a".to_vec()] == Value::Int(-42));
+        assert!(dict[&b"b".to_vec()] == Value::List(vec![
+            Value::Bytes(vec![0xff]),
+            Value::Int(7),
+        ]))
+    }
+
+    #[test]
+    fn de_map_unknown_fields()
+    {
+        use crate::de;
+        let example = "d1:a5:hello1:bli1eld1:xi2eeee1:ci3ee";
+        let example_de: TestStruct = de::from_str(example).unwrap();
+        assert!(example_de.a == "hello")
+    }
+
+    #[test]
+    fn de_untagged()
+    {
+        use crate::de;
+        #[derive(Deserialize, PartialEq, Debug)]
+        #[serde(untagged)]
+        enum Id
+        {
+            Num(i64),
+            Name(String),
+            List(Vec<Id>),
+        }
+        let example_de: Id = de::from_str("li3e4:fourlee").unwrap();
+        assert!(example_de == Id::List(vec![
+            Id::Num(3),
+            Id::Name("four".to_string()),
+            Id::List(vec![]),
+        ]))
+    }
+
+    #[test]
+    fn de_flatten()
+    {
+        use crate::{de, value::Value};
+        use std::collections::BTreeMap;
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Flattened
+        {
+            a: i32,
+            #[serde(flatten)]
+            rest: BTreeMap<String, Value>,
+        }
+        let example_de: Flattened = de::from_str("d1:ai1e1:bli2ee1:c1:xe").unwrap();
+        assert!(example_de.a == 1);
+        assert!(example_de.rest["b"] == Value::List(vec![Value::Int(2)]));
+        assert!(example_de.rest["c"] == Value::Bytes(b"x".to_vec()))
+    }
+
+    #[test]
+    fn de_ignored_any()
+    {
+        use crate::de;
+        use serde::de::IgnoredAny;
+        let example: &[u8] = b"d1:ad1:xl4:\xff\x00\x01\x02i-12eee1:bi0ee";
+        let _: IgnoredAny = de::from_bytes(example).unwrap();
+        let example_de: TestStructInt = de::from_bytes(b"d1:ai10e1:bd1:xl4:\xff\x00\x01\x02i-12eeee").unwrap();
+        assert!(example_de.a == 10);
+        // Unknown keys don't have to be UTF-8, whether read from a slice, a
+        // reader or a `Value`.
+        let example: &[u8] = b"d1:ai1e2:\xff\xfei2ee";
+        assert!(de::from_bytes::<TestStructInt>(example).unwrap().a == 1);
+        assert!(de::from_reader::<_, TestStructInt>(example).unwrap().a == 1);
+        let v = de::from_bytes::<crate::value::Value>(example).unwrap();
+        assert!(crate::value::from_value::<TestStructInt>(v).unwrap().a == 1)
+    }
+
+    #[test]
+    fn de_ignored_any_malformed()
+    {
+        use crate::de;
+        use crate::error::ErrorKind;
+        use serde::de::IgnoredAny;
+        assert!(de::from_str::<IgnoredAny>("li1e").unwrap_err().kind() == &ErrorKind::Eof);
+        assert!(de::from_str::<IgnoredAny>("i1x").unwrap_err().kind() == &ErrorKind::UnexpectedChar);
+        assert!(de::from_str::<IgnoredAny>("x").unwrap_err().kind() == &ErrorKind::Syntax);
+        assert!(de::from_str::<TestStructInt>("d1:ai1e1:be").unwrap_err().kind() == &ErrorKind::Syntax);
+        // Skipped dictionaries need byte string keys, each with a value, just
+        // like decoded ones.
+        assert!(de::from_str::<IgnoredAny>("d1:ae").unwrap_err().kind() == &ErrorKind::Syntax);
+        assert!(de::from_str::<IgnoredAny>("di1ei2ee").unwrap_err().kind() == &ErrorKind::KeyMustBeAString);
+        assert!(de::from_str::<IgnoredAny>("ld1:ai1e1:bee").unwrap_err().kind() == &ErrorKind::Syntax);
+        assert!(de::from_str::<TestStructInt>("d1:ai1e1:bd1:cee").unwrap_err().kind() == &ErrorKind::Syntax);
+        assert!(de::from_str::<TestStructInt>("d1:ai1e1:bdi1ei2eee").unwrap_err().kind() == &ErrorKind::KeyMustBeAString);
+        let err = de::from_str::<crate::value::Value>("d1:ae").unwrap_err();
+        assert!(err.kind() == &ErrorKind::Syntax)
+    }
+
+    #[test]
+    fn char_round_trip()
+    {
+        use crate::{de, ser};
+        use std::collections::BTreeMap;
+        assert!(ser::to_bytes(&'c').unwrap() == b"1:c");
+        assert!(ser::to_bytes(&'é').unwrap() == "2:é".as_bytes());
+        assert!(de::from_bytes::<char>(b"1:c").unwrap() == 'c');
+        assert!(de::from_bytes::<char>("2:é".as_bytes()).unwrap() == 'é');
+        let err = de::from_bytes::<Vec<char>>(b"l1:a2:abe").unwrap_err();
+        assert!(err.offset() == Some(4) && err.path() == Some("[1]"));
+        assert!(de::from_bytes::<char>(b"0:").is_err());
+        let mut map = BTreeMap::new();
+        map.insert('b', 'y');
+        map.insert('a', 'x');
+        let example_ser = ser::to_bytes(&map).unwrap();
+        assert!(example_ser == b"d1:a1:x1:b1:ye");
+        assert!(de::from_bytes::<BTreeMap<char, char>>(&example_ser).unwrap() == map)
+    }
+
+    #[test]
+    fn floats_unsupported()
+    {
+        use crate::{de, ser, ErrorKind};
+        assert!(ser::to_bytes(&1.5f64).unwrap_err().kind() == &ErrorKind::FloatUnsupported);
+        assert!(ser::to_bytes(&vec![1.5f32]).unwrap_err().kind() == &ErrorKind::FloatUnsupported);
+        assert!(de::from_bytes::<f64>(b"i1e").unwrap_err().kind() == &ErrorKind::FloatUnsupported);
+        assert!(de::from_bytes::<f32>(b"3:1.5").unwrap_err().kind() == &ErrorKind::FloatUnsupported)
+    }
+
+    #[test]
+    fn ser_int_widths()
+    {
+        use crate::ser;
+        assert!(ser::to_string(&255u8).unwrap() == "i255e");
+        assert!(ser::to_string(&-32768i16).unwrap() == "i-32768e");
+        assert!(ser::to_string(&u64::MAX).unwrap() == "i18446744073709551615e");
+        assert!(ser::to_string(&i128::MIN).unwrap() == "i-170141183460469231731687303715884105728e");
+        assert!(ser::to_string(&u128::MAX).unwrap() == "i340282366920938463463374607431768211455e")
+    }
+
+    #[test]
+    fn de_int_widths()
+    {
+        use crate::de;
+        assert!(de::from_str::<u8>("i255e").unwrap() == 255);
+        assert!(de::from_str::<i8>("i-128e").unwrap() == -128);
+        assert!(de::from_str::<i64>("i-9223372036854775808e").unwrap() == i64::MIN);
+        assert!(de::from_str::<u64>("i18446744073709551615e").unwrap() == u64::MAX);
+        assert!(de::from_str::<i128>("i-170141183460469231731687303715884105728e").unwrap() == i128::MIN);
+        assert!(de::from_str::<u128>("i340282366920938463463374607431768211455e").unwrap() == u128::MAX)
+    }
+
+    #[test]
+    fn de_int_overflow()
+    {
+        use crate::de;
+        use crate::error::ErrorKind;
+        assert!(de::from_str::<u8>("i256e").unwrap_err().kind() == &ErrorKind::IntegerOverflow);
+        assert!(de::from_str::<i8>("i-129e").unwrap_err().kind() == &ErrorKind::IntegerOverflow);
+        assert!(de::from_str::<u32>("i-1e").unwrap_err().kind() == &ErrorKind::IntegerOverflow);
+        assert!(de::from_str::<i64>("i9223372036854775808e").unwrap_err().kind() == &ErrorKind::IntegerOverflow);
+        assert!(de::from_str::<u128>("i340282366920938463463374607431768211456e").unwrap_err().kind() == &ErrorKind::IntegerOverflow);
+        assert!(de::from_str::<i128>("i-170141183460469231731687303715884105729e").unwrap_err().kind() == &ErrorKind::IntegerOverflow)
+    }
+
+    #[test]
+    fn value_int_widths()
+    {
+        use crate::value::{self, Value};
+        use crate::error::ErrorKind;
+        assert!(value::to_value(7u16).unwrap() == Value::Int(7));
+        assert!(value::to_value(u64::MAX).unwrap_err().kind() == &ErrorKind::IntegerOverflow);
+        assert!(value::from_value::<u8>(Value::Int(300)).is_err());
+        assert!(value::from_value::<u64>(Value::Int(1 << 40)).unwrap() == 1 << 40)
+    }
+
+    #[test]
+    fn de_int_canonical()
+    {
+        use crate::de;
+        use crate::error::ErrorKind;
+        use serde::de::IgnoredAny;
+        assert!(de::from_str::<i64>("i0e").unwrap() == 0);
+        assert!(de::from_str::<i64>("i-10e").unwrap() == -10);
+        assert!(de::from_str::<i64>("i03e").unwrap_err().kind() == &ErrorKind::LeadingZero);
+        assert!(de::from_str::<i64>("i00e").unwrap_err().kind() == &ErrorKind::LeadingZero);
+        assert!(de::from_str::<i64>("i-0e").unwrap_err().kind() == &ErrorKind::NegativeZero);
+        assert!(de::from_str::<i64>("i-03e").unwrap_err().kind() == &ErrorKind::LeadingZero);
+        assert!(de::from_str::<i64>("i-e").unwrap_err().kind() == &ErrorKind::ExpectedInteger);
+        assert!(de::from_str::<i64>("ie").unwrap_err().kind() == &ErrorKind::ExpectedInteger);
+        assert!(de::from_str::<IgnoredAny>("li03ee").unwrap_err().kind() == &ErrorKind::LeadingZero);
+        assert!(de::from_str::<IgnoredAny>("li-0ee").unwrap_err().kind() == &ErrorKind::NegativeZero)
+    }
+
+    #[test]
+    fn de_int_lenient()
+    {
+        use crate::{config::Config, de::Deserializer};
+        use serde::Deserialize;
+        let mut deserializer = Deserializer::from_str("li03ei-0ei-007ee")
+            .with_config(Config::new().lenient_integers(true));
+        let example_de = Vec::<i32>::deserialize(&mut deserializer).unwrap();
+        deserializer.end().unwrap();
+        assert!(example_de == vec![3, 0, -7])
+    }
+
+    #[test]
+    fn de_string_length_checked()
+    {
+        use crate::de;
+        use crate::error::ErrorKind;
+        use serde::de::IgnoredAny;
+        assert!(de::from_str::<String>("99:abc").unwrap_err().kind() == &ErrorKind::Eof);
+        assert!(de::from_str::<IgnoredAny>("l99:abce").unwrap_err().kind() == &ErrorKind::Eof);
+        let huge = format!("{}0:", usize::MAX);
+        assert!(de::from_str::<String>(&huge).unwrap_err().kind() == &ErrorKind::LengthOverflow)
+    }
+
+    #[test]
+    fn de_truncated_never_panics()
+    {
+        use crate::{de, value::Value};
+        use serde::de::IgnoredAny;
+        let example: &[u8] = b"d1:ad1:xl4:\xff\x00\x01\x02i-12eee1:bi0e1:c5:helloe";
+        for len in 0..example.len()
+        {
+            assert!(de::from_bytes::<Value>(&example[..len]).is_err());
+            assert!(de::from_bytes::<IgnoredAny>(&example[..len]).is_err());
+            assert!(de::from_bytes::<TestStructMap>(&example[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn ser_map_sorted_keys()
+    {
+        use crate::ser;
+        #[derive(Serialize)]
+        struct Unsorted
+        {
+            b: i32,
+            #[serde(rename = "a2")]
+            a_two: i32,
+            a: i32,
+            #[serde(rename = "\u{e9}")]
+            e_acute: i32,
+        }
+        let example = Unsorted {
+            b: 1,
+            a_two: 2,
+            a: 3,
+            e_acute: 4,
+        };
+        let example_ser = ser::to_string(&example).unwrap();
+        assert!(example_ser == "d1:ai3e2:a2i2e1:bi1e2:\u{e9}i4ee")
+    }
+
+    #[test]
+    fn ser_map_hashmap_sorted()
+    {
+        use crate::ser;
+        use std::collections::HashMap;
+        let mut example = HashMap::new();
+        for key in &["zeta", "alpha", "mu", "beta", "a", "b"]
+        {
+            example.insert(key.to_string(), key.len());
+        }
+        let example_ser = ser::to_string(&example).unwrap();
+        assert!(example_ser == "d1:ai1e5:alphai5e1:bi1e4:betai4e2:mui2e4:zetai4ee")
+    }
+
+    #[test]
+    fn ser_map_duplicate_key()
+    {
+        use crate::{ser, value};
+        use crate::error::ErrorKind;
+        use std::collections::BTreeMap;
+        #[derive(Serialize)]
+        struct Flattened
+        {
+            a: i32,
+            #[serde(flatten)]
+            rest: BTreeMap<String, i32>,
+        }
+        let mut rest = BTreeMap::new();
+        rest.insert("a".to_string(), 2);
+        let example = Flattened {
+            a: 1,
+            rest,
+        };
+        assert!(ser::to_bytes(&example).unwrap_err().kind() == &ErrorKind::DuplicateKey);
+        assert!(value::to_value(&example).unwrap_err().kind() == &ErrorKind::DuplicateKey)
+    }
+
+    #[test]
+    fn map_round_trip()
+    {
+        use crate::{de, ser};
+        use std::collections::HashMap;
+        let mut example: HashMap<String, TestStructVec> = HashMap::new();
+        example.insert("b".to_string(), TestStructVec {
+            a: vec!["x".to_string()]
+        });
+        example.insert("a".to_string(), TestStructVec {
+            a: vec![]
+        });
+        let example_ser = ser::to_string(&example).unwrap();
+        assert!(example_ser == "d1:ad1:alee1:bd1:al1:xeee");
+        let example_de: HashMap<String, TestStructVec> = de::from_str(&example_ser).unwrap();
+        assert!(example_de == example)
+    }
+
+    #[test]
+    fn ser_map_key_types()
+    {
+        use crate::{ser, value};
+        use crate::error::ErrorKind;
+        use std::collections::BTreeMap;
+        #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+        struct PeerId(String);
+        #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+        enum Kind
+        {
+            Seed,
+        }
+
+        let mut newtype_keys = BTreeMap::new();
+        newtype_keys.insert(PeerId("peer".to_string()), 1);
+        assert!(ser::to_string(&newtype_keys).unwrap() == "d4:peeri1ee");
+
+        let mut variant_keys = BTreeMap::new();
+        variant_keys.insert(Kind::Seed, 1);
+        assert!(ser::to_string(&variant_keys).unwrap() == "d4:Seedi1ee");
+
+        let mut byte_keys = BTreeMap::new();
+        byte_keys.insert(serde_bytes::ByteBuf::from(vec![0xff]), 1);
+        assert!(ser::to_bytes(&byte_keys).unwrap() == b"d1:\xffi1ee");
+
+        let mut char_keys = BTreeMap::new();
+        char_keys.insert('c', 1);
+        assert!(ser::to_string(&char_keys).unwrap() == "d1:ci1ee");
+
+        let mut int_keys = BTreeMap::new();
+        int_keys.insert(1, 1);
+        assert!(ser::to_bytes(&int_keys).unwrap_err().kind() == &ErrorKind::KeyMustBeAString);
+        assert!(value::to_value(&int_keys).unwrap_err().kind() == &ErrorKind::KeyMustBeAString);
+
+        let mut seq_keys = BTreeMap::new();
+        seq_keys.insert(vec!["a".to_string()], 1);
+        assert!(ser::to_bytes(&seq_keys).unwrap_err().kind() == &ErrorKind::KeyMustBeAString)
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    enum TestEnum
+    {
+        Ping,
+        Response(TestStruct),
+        Error(i64, String),
+        Query { q: String, id: String },
+    }
+
+    #[test]
+    fn ser_enum()
+    {
+        use crate::ser;
+        assert!(ser::to_string(&TestEnum::Ping).unwrap() == "4:Ping");
+        let example = TestEnum::Response(TestStruct {
+            a: "hello".to_string()
+        });
+        assert!(ser::to_string(&example).unwrap() == "d8:Responsed1:a5:helloee");
+        let example = TestEnum::Error(201, "Generic Error".to_string());
+        assert!(ser::to_string(&example).unwrap() == "d5:Errorli201e13:Generic Erroree");
+        let example = TestEnum::Query {
+            q: "ping".to_string(),
+            id: "abcdefghij0123456789".to_string(),
+        };
+        assert!(ser::to_string(&example).unwrap() == "d5:Queryd2:id20:abcdefghij01234567891:q4:pingee")
+    }
+
+    #[test]
+    fn de_enum()
+    {
+        use crate::de;
+        assert!(de::from_str::<TestEnum>("4:Ping").unwrap() == TestEnum::Ping);
+        assert!(de::from_str::<TestEnum>("d8:Responsed1:a5:helloee").unwrap() == TestEnum::Response(TestStruct {
+            a: "hello".to_string()
+        }));
+        assert!(de::from_str::<TestEnum>("d5:Errorli201e13:Generic Erroree").unwrap() == TestEnum::Error(201, "Generic Error".to_string()));
+        assert!(de::from_str::<TestEnum>("d5:Queryd2:id2:aa1:q4:pingee").unwrap() == TestEnum::Query {
+            q: "ping".to_string(),
+            id: "aa".to_string(),
+        })
+    }
+
+    #[test]
+    fn de_enum_malformed()
+    {
+        use crate::de;
+        use crate::error::ErrorKind;
+        assert!(de::from_str::<TestEnum>("i1e").unwrap_err().kind() == &ErrorKind::ExpectedEnum);
+        assert!(de::from_str::<TestEnum>("d4:Pingi0ee").is_err());
+        assert!(de::from_str::<TestEnum>("4:Pong").is_err());
+        assert!(de::from_str::<TestEnum>("d5:Errorli1e1:xe4:Pingi0ee").unwrap_err().kind() == &ErrorKind::ExpectedMapEnd)
+    }
+
+    #[test]
+    fn value_enum_round_trip()
+    {
+        use crate::{de, value};
+        let examples = vec![
+            TestEnum::Ping,
+            TestEnum::Response(TestStruct {
+                a: "hello".to_string()
+            }),
+            TestEnum::Error(-1, "oops".to_string()),
+            TestEnum::Query {
+                q: "ping".to_string(),
+                id: "aa".to_string(),
+            },
+        ];
+        for example in examples
+        {
+            let example_value = value::to_value(&example).unwrap();
+            assert!(de::from_bytes::<value::Value>(&crate::to_bytes(&example).unwrap()).unwrap() == example_value);
+            assert!(value::from_value::<TestEnum>(example_value).unwrap() == example);
+        }
+    }
+
+    // Mainline DHT (BEP 5) messages, discriminated on the `y` key.
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    #[serde(tag = "y")]
+    enum Krpc
+    {
+        #[serde(rename = "q")]
+        Query
+        {
+            t: serde_bytes::ByteBuf,
+            #[serde(flatten)]
+            query: KrpcQuery,
+            // BEP 43 read-only nodes. The enum is buffered by serde, which
+            // won't take `i1e` for a `bool` on its own.
+            #[serde(default, skip_serializing_if = "is_false",
+                    deserialize_with = "crate::de::bool_from_integer")]
+            ro: bool,
+        },
+        #[serde(rename = "r")]
+        Response
+        {
+            t: serde_bytes::ByteBuf,
+            r: KrpcResponse,
+        },
+        #[serde(rename = "e")]
+        Error
+        {
+            t: serde_bytes::ByteBuf,
+            e: Vec<KrpcErrorItem>,
+        },
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    #[serde(tag = "q", content = "a")]
+    enum KrpcQuery
+    {
+        #[serde(rename = "ping")]
+        Ping
+        {
+            id: serde_bytes::ByteBuf,
+        },
+        #[serde(rename = "find_node")]
+        FindNode
+        {
+            id: serde_bytes::ByteBuf,
+            target: serde_bytes::ByteBuf,
+        },
+        #[serde(rename = "get_peers")]
+        GetPeers
+        {
+            id: serde_bytes::ByteBuf,
+            info_hash: serde_bytes::ByteBuf,
+        },
+    }
+
+    // Responses don't say which query they answer, so try the most specific
+    // shape first.
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    #[serde(untagged)]
+    enum KrpcResponse
+    {
+        Peers
+        {
+            id: serde_bytes::ByteBuf,
+            token: serde_bytes::ByteBuf,
+            values: Vec<serde_bytes::ByteBuf>,
+        },
+        Nodes
+        {
+            id: serde_bytes::ByteBuf,
+            nodes: serde_bytes::ByteBuf,
+        },
+        Ping
+        {
+            id: serde_bytes::ByteBuf,
+        },
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    #[serde(untagged)]
+    enum KrpcErrorItem
+    {
+        Code(i64),
+        Message(String),
+    }
+
+    fn is_false(b: &bool) -> bool
+    {
+        !*b
+    }
+
+    fn krpc_round_trip(encoded: &[u8], expected: Krpc)
+    {
+        use crate::{de, ser, value};
+        let decoded: Krpc = de::from_bytes(encoded).unwrap();
+        assert!(decoded == expected);
+        assert!(ser::to_bytes(&decoded).unwrap() == encoded);
+        assert!(value::from_value::<Krpc>(value::to_value(&decoded).unwrap()).unwrap() == expected)
+    }
+
+    fn byte_buf(bytes: &[u8]) -> serde_bytes::ByteBuf
+    {
+        serde_bytes::ByteBuf::from(bytes.to_vec())
+    }
+
+    #[test]
+    fn krpc_ping()
+    {
+        krpc_round_trip(b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe", Krpc::Query {
+            t: byte_buf(b"aa"),
+            query: KrpcQuery::Ping {
+                id: byte_buf(b"abcdefghij0123456789"),
+            },
+            ro: false,
+        });
+        krpc_round_trip(b"d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re", Krpc::Response {
+            t: byte_buf(b"aa"),
+            r: KrpcResponse::Ping {
+                id: byte_buf(b"mnopqrstuvwxyz123456"),
+            },
+        })
+    }
+
+    #[test]
+    fn krpc_find_node()
+    {
+        krpc_round_trip(b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q9:find_node1:t2:aa1:y1:qe", Krpc::Query {
+            t: byte_buf(b"aa"),
+            query: KrpcQuery::FindNode {
+                id: byte_buf(b"abcdefghij0123456789"),
+                target: byte_buf(b"mnopqrstuvwxyz123456"),
+            },
+            ro: false,
+        });
+        krpc_round_trip(b"d1:rd2:id20:0123456789abcdefghij5:nodes9:def456...e1:t2:aa1:y1:re", Krpc::Response {
+            t: byte_buf(b"aa"),
+            r: KrpcResponse::Nodes {
+                id: byte_buf(b"0123456789abcdefghij"),
+                nodes: byte_buf(b"def456..."),
+            },
+        })
+    }
+
+    #[test]
+    fn krpc_get_peers()
+    {
+        krpc_round_trip(b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e1:q9:get_peers1:t2:aa1:y1:qe", Krpc::Query {
+            t: byte_buf(b"aa"),
+            query: KrpcQuery::GetPeers {
+                id: byte_buf(b"abcdefghij0123456789"),
+                info_hash: byte_buf(b"mnopqrstuvwxyz123456"),
+            },
+            ro: false,
+        });
+        // Compact peer info is raw binary, not UTF-8.
+        krpc_round_trip(b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:\x7f\x00\x00\x01\x1a\xe16:\xc0\xa8\x00\x02\xc8\xd5ee1:t2:aa1:y1:re", Krpc::Response {
+            t: byte_buf(b"aa"),
+            r: KrpcResponse::Peers {
+                id: byte_buf(b"abcdefghij0123456789"),
+                token: byte_buf(b"aoeusnth"),
+                values: vec![
+                    byte_buf(b"\x7f\x00\x00\x01\x1a\xe1"),
+                    byte_buf(b"\xc0\xa8\x00\x02\xc8\xd5"),
+                ],
+            },
+        })
+    }
+
+    #[test]
+    fn krpc_error()
+    {
+        krpc_round_trip(b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee", Krpc::Error {
+            t: byte_buf(b"aa"),
+            e: vec![
+                KrpcErrorItem::Code(201),
+                KrpcErrorItem::Message("A Generic Error Ocurred".to_string()),
+            ],
+        })
+    }
+
+    #[test]
+    fn krpc_read_only()
+    {
+        use crate::de;
+        let encoded = b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping2:roi1e1:t2:aa1:y1:qe";
+        let expected = Krpc::Query {
+            t: byte_buf(b"aa"),
+            query: KrpcQuery::Ping {
+                id: byte_buf(b"abcdefghij0123456789"),
+            },
+            ro: true,
+        };
+        krpc_round_trip(encoded, expected);
+        assert!(de::from_reader::<_, Krpc>(&encoded[..]).unwrap() == Krpc::Query {
+            t: byte_buf(b"aa"),
+            query: KrpcQuery::Ping {
+                id: byte_buf(b"abcdefghij0123456789"),
+            },
+            ro: true,
+        });
+        assert!(de::from_bytes::<Krpc>(b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping2:roi2e1:t2:aa1:y1:qe").is_err())
+    }
+
+    #[test]
+    fn krpc_unknown_type()
+    {
+        use crate::de;
+        assert!(de::from_bytes::<Krpc>(b"d1:t2:aa1:y1:xe").is_err());
+        assert!(de::from_bytes::<Krpc>(b"d1:t2:aae").is_err())
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct TestStructOption
+    {
+        announce: String,
+        #[serde(rename = "announce-list")]
+        announce_list: Option<Vec<Vec<String>>>,
+        comment: Option<String>,
+        private: Option<i64>,
+    }
+
+    #[test]
+    fn ser_option()
+    {
+        use crate::{ser, value};
+        let example = TestStructOption {
+            announce: "a".to_string(),
+            announce_list: None,
+            comment: Some("c".to_string()),
+            private: None,
+        };
+        assert!(ser::to_string(&example).unwrap() == "d8:announce1:a7:comment1:ce");
+        let example_value = value::to_value(&example).unwrap();
+        assert!(example_value.as_dict().unwrap().len() == 2);
+        assert!(value::from_value::<TestStructOption>(example_value).unwrap() == example);
+        assert!(ser::to_string(&Some(3)).unwrap() == "i3e")
+    }
+
+    #[test]
+    fn de_option()
+    {
+        use crate::de;
+        let example_de: TestStructOption = de::from_str("d8:announce1:a13:announce-listll1:a1:bee7:privatei1ee").unwrap();
+        assert!(example_de == TestStructOption {
+            announce: "a".to_string(),
+            announce_list: Some(vec![vec!["a".to_string(), "b".to_string()]]),
+            comment: None,
+            private: Some(1),
+        });
+        assert!(de::from_str::<Option<i32>>("i3e").unwrap() == Some(3))
+    }
+
+    #[test]
+    fn ser_none_unrepresentable()
+    {
+        use crate::{ser, value};
+        use crate::error::ErrorKind;
+        use std::collections::BTreeMap;
+        assert!(ser::to_bytes(&None::<i32>).unwrap_err().kind() == &ErrorKind::UnexpectedNone);
+        assert!(ser::to_bytes(&vec![Some(1), None]).unwrap_err().kind() == &ErrorKind::UnexpectedNone);
+        assert!(value::to_value(vec![None::<i32>]).unwrap_err().kind() == &ErrorKind::UnexpectedNone);
+
+        #[derive(Serialize)]
+        struct Nested
+        {
+            a: Vec<Option<i32>>,
+        }
+        let example = Nested {
+            a: vec![None],
+        };
+        assert!(ser::to_bytes(&example).unwrap_err().kind() == &ErrorKind::UnexpectedNone);
+        assert!(value::to_value(&example).unwrap_err().kind() == &ErrorKind::UnexpectedNone);
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), None);
+        map.insert("b".to_string(), Some(2));
+        assert!(ser::to_string(&map).unwrap() == "d1:bi2ee");
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), Some(None::<i32>));
+        assert!(ser::to_string(&map).unwrap() == "de");
+        assert!(value::to_value(&map).unwrap() == value::Value::Dict(BTreeMap::new()))
+    }
+
+    #[test]
+    fn ser_dict_values_serialized_once()
+    {
+        use crate::{ser, value};
+        use serde::Serializer;
+        use std::cell::Cell;
+        struct Counted<'a>(&'a Cell<usize>);
+        impl<'a> Serialize for Counted<'a>
+        {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            {
+                self.0.set(self.0.get() + 1);
+                serializer.serialize_i64(1)
+            }
+        }
+        let calls = Cell::new(0);
+        let mut map = std::collections::BTreeMap::new();
+        map.insert("a", Counted(&calls));
+        assert!(ser::to_string(&map).unwrap() == "d1:ai1ee");
+        assert!(calls.get() == 1);
+        value::to_value(&map).unwrap();
+        assert!(calls.get() == 2)
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct TestStructBool
+    {
+        private: bool,
+    }
+
+    #[test]
+    fn ser_bool()
+    {
+        use crate::{ser, value};
+        assert!(ser::to_string(&true).unwrap() == "i1e");
+        assert!(ser::to_string(&vec![false, true]).unwrap() == "li0ei1ee");
+        let example = TestStructBool {
+            private: true,
+        };
+        assert!(ser::to_string(&example).unwrap() == "d7:privatei1ee");
+        assert!(value::to_value(false).unwrap() == value::Value::Int(0))
+    }
+
+    #[test]
+    fn de_bool()
+    {
+        use crate::{de, value};
+        use crate::error::ErrorKind;
+        assert!(!de::from_str::<bool>("i0e").unwrap());
+        assert!(de::from_str::<TestStructBool>("d7:privatei1ee").unwrap().private);
+        assert!(de::from_str::<bool>("i2e").unwrap_err().kind() == &ErrorKind::InvalidBool);
+        assert!(de::from_str::<bool>("i-1e").unwrap_err().kind() == &ErrorKind::InvalidBool);
+        assert!(value::from_value::<bool>(value::Value::Int(1)).unwrap());
+        assert!(value::from_value::<bool>(value::Value::Int(5)).unwrap_err().kind() == &ErrorKind::InvalidBool)
+    }
+
+    #[test]
+    fn bool_strict_config()
+    {
+        use crate::{config::Config, de::Deserializer, ser::Serializer};
+        use crate::error::ErrorKind;
+        let config = Config::new().bools_as_integers(false);
+        let example = TestStructBool {
+            private: true,
+        };
+        let mut serializer = Serializer::new().with_config(config);
+        assert!(example.serialize(&mut serializer).unwrap_err().kind() == &ErrorKind::BoolUnsupported);
+        let mut deserializer = Deserializer::from_str("d7:privatei1ee").with_config(config);
+        assert!(TestStructBool::deserialize(&mut deserializer).unwrap_err().kind() == &ErrorKind::BoolUnsupported)
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct TestTupleStruct(String, i64);
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct TestStructNodes
+    {
+        nodes: Vec<(String, u16)>,
+    }
+
+    #[test]
+    fn ser_tuple()
+    {
+        use crate::{ser, value};
+        assert!(ser::to_string(&(1, "a")).unwrap() == "li1e1:ae");
+        assert!(ser::to_string(&TestTupleStruct("b".to_owned(), -3)).unwrap() == "l1:bi-3ee");
+        assert!(ser::to_string(&[1u8, 2, 3]).unwrap() == "li1ei2ei3ee");
+        let example = TestStructNodes {
+            nodes: vec![("router.example".to_owned(), 6881)],
+        };
+        let expected = "d5:nodesll14:router.examplei6881eeee";
+        assert!(ser::to_string(&example).unwrap() == expected);
+        assert!(value::to_value((1, "a")).unwrap() == value::Value::List(vec![
+            value::Value::Int(1),
+            value::Value::Bytes(b"a".to_vec()),
+        ]))
+    }
+
+    #[test]
+    fn de_tuple()
+    {
+        use crate::{de, value};
+        let j = "d5:nodesll14:router.examplei6881eel4:peeri1eeee";
+        let expected = TestStructNodes {
+            nodes: vec![("router.example".to_owned(), 6881), ("peer".to_owned(), 1)],
+        };
+        assert!(de::from_str::<TestStructNodes>(j).unwrap() == expected);
+        assert!(de::from_str::<TestTupleStruct>("l1:bi-3ee").unwrap() == TestTupleStruct("b".to_owned(), -3));
+        assert!(de::from_str::<[u8; 3]>("li1ei2ei3ee").unwrap() == [1, 2, 3]);
+        let v = value::Value::List(vec![value::Value::Int(1), value::Value::Int(2)]);
+        assert!(value::from_value::<(i64, i64)>(v).unwrap() == (1, 2))
+    }
+
+    #[test]
+    fn de_tuple_wrong_length()
+    {
+        use crate::{de, value};
+        use crate::config::{Config, Limits};
+        use crate::error::ErrorKind;
+        let too_few = de::from_str::<(u8, u8, u8)>("li1ei2ee").unwrap_err();
+        assert!(too_few.kind() == &ErrorKind::Message("invalid length 2, expected a tuple of size 3".to_owned()));
+        let too_many = de::from_str::<[u8; 2]>("li1ei2eli3eed1:ai4eee").unwrap_err();
+        assert!(too_many.kind() == &ErrorKind::Message("invalid length 4, expected a list of 2 elements".to_owned()));
+        let mut deserializer = de::Deserializer::from_str("li1ei2ee");
+        let too_many = <[u8; 1]>::deserialize(&mut deserializer).unwrap_err();
+        assert!(too_many.offset() == Some(0));
+        // The leftovers are held to the same limits as the elements.
+        let config = Config::new().recursion_limit(1);
+        let mut deserializer = de::Deserializer::from_str("li1eli1eee").with_config(config);
+        let too_deep = <[u8; 1]>::deserialize(&mut deserializer).unwrap_err();
+        assert!(too_deep.kind() == &ErrorKind::RecursionLimitExceeded);
+        let config = Config::new().limits(Limits::new().max_entries(2));
+        let mut deserializer = de::Deserializer::from_str("li1ei2ei3ee").with_config(config);
+        let too_long = <[u8; 1]>::deserialize(&mut deserializer).unwrap_err();
+        assert!(too_long.kind() == &ErrorKind::TooManyEntries);
+        assert!(de::from_str::<TestTupleStruct>("l1:bi-3ei0ee").is_err());
+        let v = value::Value::List(vec![value::Value::Int(1)]);
+        assert!(value::from_value::<(i64, i64)>(v).is_err())
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct Port(u16);
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct PeerId(String);
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct Marker;
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct TestStructNewtype
+    {
+        peer_id: PeerId,
+        port: Port,
+        marker: Marker,
+    }
+
+    #[test]
+    fn ser_newtype_and_unit()
+    {
+        use crate::{ser, value};
+        assert!(ser::to_string(&Port(6881)).unwrap() == "i6881e");
+        assert!(ser::to_string(&()).unwrap() == "le");
+        assert!(ser::to_string(&Marker).unwrap() == "le");
+        let example = TestStructNewtype {
+            peer_id: PeerId("-XX0001-".to_owned()),
+            port: Port(6881),
+            marker: Marker,
+        };
+        let expected = "d6:markerle7:peer_id8:-XX0001-4:porti6881ee";
+        assert!(ser::to_string(&example).unwrap() == expected);
+        assert!(value::to_value(Port(1)).unwrap() == value::Value::Int(1));
+        assert!(value::to_value(()).unwrap() == value::Value::List(Vec::new()))
+    }
+
+    #[test]
+    fn de_newtype_and_unit()
+    {
+        use crate::{de, value};
+        let j = "d6:markerle7:peer_id8:-XX0001-4:porti6881ee";
+        let expected = TestStructNewtype {
+            peer_id: PeerId("-XX0001-".to_owned()),
+            port: Port(6881),
+            marker: Marker,
+        };
+        assert!(de::from_str::<TestStructNewtype>(j).unwrap() == expected);
+        de::from_str::<()>("le").unwrap();
+        assert!(de::from_str::<()>("li1ee").is_err());
+        assert!(de::from_str::<Marker>("de").is_err());
+        assert!(value::from_value::<Port>(value::Value::Int(1)).unwrap() == Port(1));
+        assert!(value::from_value::<Marker>(value::Value::List(Vec::new())).unwrap() == Marker);
+        assert!(value::from_value::<()>(value::Value::Int(0)).is_err())
+    }
+
+    #[test]
+    fn de_reader()
+    {
+        use crate::{de, value};
+        let j = b"d1:ai1e1:b4:skip1:cl2:\xff\xfeee";
+        let expected = TestStructInt {
+            a: 1,
+        };
+        assert!(de::from_reader::<_, TestStructInt>(&j[..]).unwrap() == expected);
+        let v = de::from_reader::<_, value::Value>(&j[..]).unwrap();
+        assert!(v == de::from_bytes::<value::Value>(j).unwrap());
+        let example = TestStructMap {
+            a: "x".to_owned(),
+            b: TestStruct {
+                a: "y".to_owned(),
+            },
+        };
+        let bytes = crate::ser::to_bytes(&example).unwrap();
+        assert!(de::from_reader::<_, TestStructMap>(bytes.as_slice()).unwrap() == example)
+    }
+
+    #[test]
+    fn de_reader_errors()
+    {
+        use std::io;
+        use crate::{de, value};
+        use crate::error::ErrorKind;
+        assert!(de::from_reader::<_, String>(&b"5:abc"[..]).unwrap_err().kind() == &ErrorKind::Eof);
+        assert!(de::from_reader::<_, value::Value>(&b"999999999999:"[..]).unwrap_err().kind() == &ErrorKind::Eof);
+        assert!(de::from_reader::<_, i64>(&b"i1ei2e"[..]).unwrap_err().kind() == &ErrorKind::TrailingCharacters);
+
+        struct Broken;
+        impl io::Read for Broken {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset"))
+            }
+        }
+        let err = de::from_reader::<_, value::Value>(Broken).unwrap_err();
+        assert!(err.kind() == &ErrorKind::Io(io::ErrorKind::ConnectionReset.into()))
+    }
+
+    #[test]
+    fn ser_writer()
+    {
+        use std::io;
+        use crate::ser;
+        use crate::error::ErrorKind;
+        let example = TestStructMap {
+            a: "x".to_owned(),
+            b: TestStruct {
+                a: "y".to_owned(),
+            },
+        };
+        let mut out = io::Cursor::new(Vec::new());
+        ser::to_writer(&mut out, &example).unwrap();
+        assert!(out.into_inner() == ser::to_bytes(&example).unwrap());
+
+        let mut serializer = ser::Serializer::from_writer(Vec::new());
+        vec![1, 2].serialize(&mut serializer).unwrap();
+        assert!(serializer.into_inner() == b"li1ei2ee");
+
+        // A full buffer makes `write_all` fail with `WriteZero`.
+        let mut buf = [0u8; 4];
+        let err = ser::to_writer(&mut buf[..], &example).unwrap_err();
+        assert!(err.kind() == &ErrorKind::Io(io::ErrorKind::WriteZero.into()));
+
+        struct Broken;
+        impl io::Write for Broken {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let err = ser::to_writer(Broken, &vec![1, 2]).unwrap_err();
+        assert!(err.kind() == &ErrorKind::Io(io::ErrorKind::BrokenPipe.into()));
+        let err = ser::to_writer(Broken, &example).unwrap_err();
+        assert!(err.kind() == &ErrorKind::Io(io::ErrorKind::BrokenPipe.into()))
+    }
+
+    #[test]
+    fn ser_nested_dicts_sorted()
+    {
+        use crate::ser;
+        use std::collections::BTreeMap;
+        #[derive(Serialize)]
+        struct Inner
+        {
+            z: i32,
+            a: Vec<BTreeMap<String, i32>>,
+        }
+        #[derive(Serialize)]
+        struct Outer
+        {
+            y: Inner,
+            b: (Inner, i32),
+        }
+        let mut map = BTreeMap::new();
+        map.insert("k".to_owned(), 1);
+        let example = Outer {
+            y: Inner { z: 1, a: vec![BTreeMap::new(), map.clone()] },
+            b: (Inner { z: 2, a: vec![map, BTreeMap::new()] }, 3),
+        };
+        let expected = "d1:bld1:ald1:ki1eedee1:zi2eei3ee1:yd1:alded1:ki1eee1:zi1eee";
+        assert!(ser::to_string(&example).unwrap() == expected);
+        let mut out = Vec::new();
+        ser::to_writer(&mut out, &vec![example]).unwrap();
+        assert!(out == format!("l{}e", expected).as_bytes())
+    }
+
+    #[test]
+    fn de_stream()
+    {
+        use crate::de::Deserializer;
+        use crate::value::Value;
+        let input = b"d1:ai1eei42e4:spamle";
+        let mut stream = Deserializer::from_slice(input).into_iter::<Value>();
+        let mut offsets = vec![stream.byte_offset()];
+        let mut values = Vec::new();
+        while let Some(value) = stream.next() {
+            values.push(value.unwrap());
+            offsets.push(stream.byte_offset());
+        }
+        assert!(values.len() == 4);
+        assert!(values[1] == Value::Int(42));
+        assert!(values[2] == Value::Bytes(b"spam".to_vec()));
+        assert!(values[3] == Value::List(Vec::new()));
+        assert!(offsets == vec![0, 8, 12, 18, 20]);
+
+        let mut stream = Deserializer::from_reader(&input[..]).into_iter::<Value>();
+        assert!(stream.by_ref().count() == 4);
+        assert!(stream.byte_offset() == input.len())
+    }
+
+    #[test]
+    fn de_stream_error()
+    {
+        use crate::de::Deserializer;
+        use crate::error::ErrorKind;
+        let mut stream = Deserializer::from_slice(b"i1ei2e3:abi4e").into_iter::<i64>();
+        assert!(stream.next().unwrap().unwrap() == 1);
+        assert!(stream.next().unwrap().unwrap() == 2);
+        assert!(stream.next().unwrap().unwrap_err().kind() == &ErrorKind::ExpectedI);
+        assert!(stream.byte_offset() == 6);
+        assert!(stream.next().is_none());
+
+        let mut stream = Deserializer::from_slice(b"").into_iter::<i64>();
+        assert!(stream.next().is_none())
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct TestFile
+    {
+        length: i64,
+        path: Vec<String>,
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct TestInfo
+    {
+        files: Vec<TestFile>,
+        name: String,
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct TestTorrent
+    {
+        info: TestInfo,
+    }
+
+    #[test]
+    fn error_offset_and_path()
+    {
+        use crate::de;
+        use crate::error::ErrorKind;
+        let j = "d4:infod5:filesld6:lengthi1e4:pathl1:a1:beed6:lengthi03e4:pathl1:ceee4:name1:xee";
+        let err = de::from_str::<TestTorrent>(j).unwrap_err();
+        assert!(err.kind() == &ErrorKind::LeadingZero);
+        assert!(err.offset() == j.find("i03e"));
+        assert!(err.path() == Some("info.files[1].length"));
+        let expected = format!("integer has a leading zero at byte {} (info.files[1].length)", j.find("i03e").unwrap());
+        assert!(err.to_string() == expected);
+
+        let j = b"d4:infod5:filesld6:lengthi1e4:pathl1:a1:\xffeee4:name1:xee";
+        let err = de::from_bytes::<TestTorrent>(j).unwrap_err();
+        assert!(err.kind() == &ErrorKind::InvalidUtf8);
+        assert!(err.offset() == Some(38));
+        assert!(err.path() == Some("info.files[0].path[1]"));
+
+        // Errors raised by visitors point at the start of the failing value.
+        let j = "d4:infod5:filesld4:pathl1:aeee4:name1:xee";
+        let err = de::from_str::<TestTorrent>(j).unwrap_err();
+        assert!(err.kind() == &ErrorKind::Message("missing field `length`".to_owned()));
+        assert!(err.offset() == j.find("d4:path"));
+        assert!(err.path() == Some("info.files[0]"));
+
+        let err = de::from_reader::<_, TestTorrent>(&b"d4:infod5:filesli1eeee"[..]).unwrap_err();
+        assert!(err.offset() == Some(16));
+        assert!(err.path() == Some("info.files[0]"))
+    }
+
+    #[test]
+    fn error_offset_syntax()
+    {
+        use crate::{de, ser, value};
+        use crate::error::ErrorKind;
+        let err = de::from_str::<value::Value>("d1:ai1x").unwrap_err();
+        assert!(err.kind() == &ErrorKind::UnexpectedChar);
+        assert!(err.offset() == Some(6));
+        assert!(err.path() == Some("a"));
+        assert!(err.to_string() == "expected a digit or `e` in integer at byte 6 (a)");
+
+        let err = de::from_str::<Vec<String>>("l1:a").unwrap_err();
+        assert!(err.kind() == &ErrorKind::Eof);
+        assert!(err.offset() == Some(4));
+        let err = de::from_str::<value::Value>("l3:ab").unwrap_err();
+        assert!(err.kind() == &ErrorKind::Eof);
+        assert!(err.offset() == Some(5));
+        assert!(err.path() == Some("[0]"));
+
+        let err = de::from_str::<String>("i1e").unwrap_err();
+        assert!(err.offset() == Some(0));
+        assert!(err.path().is_none());
+        let err = de::from_str::<i64>("i1ei2e").unwrap_err();
+        assert!(err.kind() == &ErrorKind::TrailingCharacters);
+        assert!(err.offset() == Some(3));
+        let err = de::from_str::<value::Value>("di1ei2ee").unwrap_err();
+        assert!(err.kind() == &ErrorKind::KeyMustBeAString);
+        assert!(err.offset() == Some(1));
+
+        let err = ser::to_bytes(&None::<i32>).unwrap_err();
+        assert!(err.offset().is_none() && err.path().is_none())
+    }
+
+    fn nested_lists(depth: usize) -> String
+    {
+        "l".repeat(depth) + &"e".repeat(depth)
+    }
+
+    #[test]
+    fn de_recursion_limit()
+    {
+        use serde::de::IgnoredAny;
+        use crate::{de, value};
+        use crate::error::ErrorKind;
+        de::from_str::<value::Value>(&nested_lists(128)).unwrap();
+        let err = de::from_str::<value::Value>(&nested_lists(129)).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+        assert!(err.offset() == Some(128));
+
+        // Deep enough to overflow the stack without the limit.
+        let hostile = "l".repeat(500_000);
+        let err = de::from_str::<value::Value>(&hostile).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+        let err = de::from_reader::<_, value::Value>(hostile.as_bytes()).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+
+        // Skipped values are held to the same limit.
+        de::from_str::<IgnoredAny>(&nested_lists(128)).unwrap();
+        let err = de::from_str::<IgnoredAny>(&hostile).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+        let j = format!("d1:ai1e1:b{}e", nested_lists(128));
+        let err = de::from_str::<TestStructInt>(&j).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+        assert!(err.offset() == Some(137))
+    }
+
+    #[test]
+    fn de_recursion_limit_config()
+    {
+        use crate::{config::Config, de::Deserializer, value::Value};
+        use crate::error::ErrorKind;
+        let config = Config::new().recursion_limit(2);
+        let mut deserializer = Deserializer::from_str("lle3:abce").with_config(config);
+        Value::deserialize(&mut deserializer).unwrap();
+        let mut deserializer = Deserializer::from_str("llleee").with_config(config);
+        let err = Value::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+        // An enum variant holding data is one level on its own.
+        let mut deserializer = Deserializer::from_str("d5:Errorli1e1:xee").with_config(config);
+        TestEnum::deserialize(&mut deserializer).unwrap();
+        let mut deserializer = Deserializer::from_str("d8:Responsed1:a1:xee").with_config(config);
+        TestEnum::deserialize(&mut deserializer).unwrap();
+        let mut deserializer = Deserializer::from_str("d8:Responsed1:a1:xee").with_config(config.recursion_limit(1));
+        let err = TestEnum::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+        assert!(err.offset() == Some(11));
+        // A failed skip gives back the levels it entered, so a caller that
+        // carries on with the rest of the input still has the full budget.
+        let config = config.recursion_limit(1).limits(crate::Limits::new().max_entries(1));
+        let mut deserializer = Deserializer::from_str("li1eli2eee").with_config(config);
+        let err = serde::de::IgnoredAny::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::TooManyEntries);
+        assert!(Vec::<i64>::deserialize(&mut deserializer).unwrap() == vec![2])
+    }
+
+    #[test]
+    fn ser_recursion_limit()
+    {
+        use crate::{config::Config, ser, value::Value};
+        use crate::error::ErrorKind;
+        let mut value = Value::List(Vec::new());
+        for _ in 1..128 {
+            value = Value::List(vec![value]);
+        }
+        assert!(ser::to_string(&value).unwrap() == nested_lists(128));
+        let mut dict = std::collections::BTreeMap::new();
+        dict.insert(b"a".to_vec(), value.clone());
+        let err = ser::to_bytes(&Value::Dict(dict)).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+        let err = ser::to_bytes(&Value::List(vec![value])).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+
+        let config = Config::new().recursion_limit(2);
+        let mut serializer = ser::Serializer::new().with_config(config);
+        TestEnum::Error(1, "x".to_owned()).serialize(&mut serializer).unwrap();
+        let mut serializer = ser::Serializer::new().with_config(config);
+        let err = vec![TestEnum::Error(1, "x".to_owned())].serialize(&mut serializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::RecursionLimitExceeded);
+        let mut serializer = ser::Serializer::new().with_config(config);
+        let query = TestEnum::Query {
+            q: "ping".to_owned(),
+            id: "aa".to_owned(),
+        };
+        query.serialize(&mut serializer).unwrap();
+        assert!(serializer.into_inner() == ser::to_bytes(&query).unwrap())
+    }
+
+    #[test]
+    fn de_limits()
+    {
+        use serde::de::IgnoredAny;
+        use crate::config::{Config, Limits};
+        use crate::de::Deserializer;
+        use crate::value::Value;
+        use crate::error::ErrorKind;
+        let config = Config::new().limits(Limits::new().max_string_length(4));
+        let mut deserializer = Deserializer::from_str("l4:spam5:eggs!e").with_config(config);
+        let err = Value::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::StringTooLong);
+        assert!(err.offset() == Some(7));
+        assert!(err.path() == Some("[1]"));
+        // Checked before reading, so a huge declared length fails right away.
+        let mut deserializer = Deserializer::from_str("99999999999:").with_config(config);
+        let err = IgnoredAny::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::StringTooLong);
+
+        let config = Config::new().limits(Limits::new().max_entries(2));
+        let mut deserializer = Deserializer::from_str("li1ei2ee").with_config(config);
+        Vec::<i64>::deserialize(&mut deserializer).unwrap();
+        let mut deserializer = Deserializer::from_str("li1ei2ei3ee").with_config(config);
+        let err = Vec::<i64>::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::TooManyEntries);
+        assert!(err.offset() == Some(7));
+        let mut deserializer = Deserializer::from_str("d1:ai1e1:bi2e1:ci3ee").with_config(config);
+        let err = Value::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::TooManyEntries);
+        // Skipped containers are held to it as well.
+        let mut deserializer = Deserializer::from_str("d1:ai1e1:bli1ei2ei3eee").with_config(config);
+        let err = TestStructInt::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::TooManyEntries);
+        assert!(err.offset() == Some(17));
+        let mut deserializer = Deserializer::from_str("d1:ai1e1:bd1:xi1e1:yi2e1:zi3eee").with_config(config);
+        let err = TestStructInt::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::TooManyEntries);
+
+        // Only byte strings copied out of a reader count towards the
+        // allocation budget.
+        let config = Config::new().limits(Limits::new().max_allocated(8));
+        let mut deserializer = Deserializer::from_reader(&b"d1:a3:xyz1:b3:xyze"[..]).with_config(config);
+        Value::deserialize(&mut deserializer).unwrap();
+        let mut deserializer = Deserializer::from_reader(&b"d1:a3:xyz1:b2:xy1:c1:-e"[..]).with_config(config);
+        let err = Value::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::AllocationLimitExceeded);
+        assert!(err.path() == Some("c"));
+        let mut deserializer = Deserializer::from_str("d1:a3:xyz1:b2:xy1:c1:-e").with_config(config);
+        Value::deserialize(&mut deserializer).unwrap();
+        // Skipped values don't count towards the allocation budget.
+        let mut deserializer = Deserializer::from_reader(&b"d1:ai1e1:b20:xxxxxxxxxxxxxxxxxxxxe"[..]).with_config(config);
+        TestStructInt::deserialize(&mut deserializer).unwrap();
+        // Raw values copy every byte they span out of a reader.
+        let config = Config::new().limits(Limits::new().max_allocated(4));
+        let mut deserializer = Deserializer::from_reader(&b"l3:abc3:defe"[..]).with_config(config);
+        let err = Vec::<serde_bytes::ByteBuf>::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::AllocationLimitExceeded);
+        let mut deserializer = Deserializer::from_reader(&b"l3:abc3:defe"[..]).with_config(config);
+        let err = Box::<crate::value::RawValue>::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::AllocationLimitExceeded);
+        assert!(err.offset() == Some(1));
+        let mut deserializer = Deserializer::from_reader(&b"li1ei2ee"[..]).with_config(config);
+        let err = Box::<crate::value::RawValue>::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::AllocationLimitExceeded);
+        let mut deserializer = Deserializer::from_slice(b"l3:abc3:defe").with_config(config);
+        <&crate::value::RawValue>::deserialize(&mut deserializer).unwrap();
+        let config = Config::new().limits(Limits::new().max_allocated(8));
+        // And the budget is per value when streaming.
+        let stream = Deserializer::from_reader(&b"5:hello5:world"[..]).with_config(config).into_iter::<Value>();
+        assert!(stream.collect::<crate::Result<Vec<_>>>().unwrap().len() == 2);
+
+        let config = Config::new().limits(Limits::new().max_input_size(8));
+        let mut deserializer = Deserializer::from_str("l3:abce").with_config(config);
+        Value::deserialize(&mut deserializer).unwrap();
+        let mut deserializer = Deserializer::from_str("li1ei2ei3ee").with_config(config);
+        let err = Value::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::InputTooLarge);
+        assert!(err.offset() == Some(8));
+        // A byte string running past the limit fails before it is read.
+        let mut deserializer = Deserializer::from_reader(&b"l99999:"[..]).with_config(config);
+        let err = Value::deserialize(&mut deserializer).unwrap_err();
+        assert!(err.kind() == &ErrorKind::InputTooLarge);
+        assert!(err.offset() == Some(1));
+        let mut deserializer = Deserializer::from_str("i1ei2e").with_config(config);
+        assert!(i64::deserialize(&mut deserializer).unwrap() == 1);
+        assert!(deserializer.end().unwrap_err().kind() == &ErrorKind::TrailingCharacters);
+        let stream = Deserializer::from_str("4:spam4:eggs").with_config(config).into_iter::<Value>();
+        assert!(stream.collect::<crate::Result<Vec<_>>>().unwrap().len() == 2)
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct TestStructPieces<'a>
+    {
+        #[serde(with = "serde_bytes")]
+        pieces: &'a [u8],
+        #[serde(borrow, with = "serde_bytes")]
+        name: std::borrow::Cow<'a, [u8]>,
+    }
+
+    #[test]
+    fn de_borrowed_bytes()
+    {
+        use std::borrow::Cow;
+        use crate::{de, ser};
+        let input = b"d4:name3:foo6:pieces4:\x00\xff\x13\x37e";
+        let example = de::from_bytes::<TestStructPieces>(input).unwrap();
+        assert!(example.pieces == b"\x00\xff\x13\x37");
+        let range = input.as_ptr_range();
+        assert!(range.contains(&example.pieces.as_ptr()));
+        match example.name {
+            Cow::Borrowed(name) => assert!(range.contains(&name.as_ptr())),
+            Cow::Owned(_) => panic!("name was copied"),
+        }
+        assert!(ser::to_bytes(&example).unwrap() == input);
+
+        // Plain `&[u8]` borrows too, and dictionary keys can be bytes.
+        let list = de::from_bytes::<Vec<&[u8]>>(b"l2:\xff\xfe0:e").unwrap();
+        assert!(list == vec![&b"\xff\xfe"[..], &b""[..]]);
+        let dict = de::from_bytes::<std::collections::BTreeMap<&[u8], i64>>(b"d1:\xffi1ee").unwrap();
+        assert!(dict[&b"\xff"[..]] == 1)
+    }
+
+    #[test]
+    fn de_byte_buf()
+    {
+        use crate::{de, value};
+        use serde_bytes::ByteBuf;
+        let input = b"l4:\x00\xff\x13\x374:spame";
+        let expected = vec![ByteBuf::from(b"\x00\xff\x13\x37".to_vec()), ByteBuf::from(b"spam".to_vec())];
+        assert!(de::from_bytes::<Vec<ByteBuf>>(input).unwrap() == expected);
+        assert!(de::from_reader::<_, Vec<ByteBuf>>(&input[..]).unwrap() == expected);
+        // Nothing can be borrowed from a stream.
+        assert!(de::from_reader::<_, serde_bytes::ByteBuf>(&b"3:abc"[..]).unwrap() == b"abc".to_vec());
+        let v = value::Value::Bytes(b"spam".to_vec());
+        assert!(value::from_value::<ByteBuf>(v).unwrap() == b"spam".to_vec())
+    }
+
+    #[derive(Deserialize, Serialize, Debug)]
+    struct TestTorrentRaw<'a>
+    {
+        announce: String,
+        #[serde(borrow)]
+        info: &'a crate::RawValue,
+    }
+
+    #[test]
+    fn raw_value()
+    {
+        use crate::{de, ser, value, ErrorKind, RawValue};
+        // The keys of `info` are out of order, so re-encoding it would change
+        // its bytes.
+        let input = b"d8:announce3:url4:infod4:name1:a6:lengthi3eee";
+        let torrent = de::from_bytes::<TestTorrentRaw>(input).unwrap();
+        assert!(torrent.info.get() == b"d4:name1:a6:lengthi3ee");
+        assert!(input.as_ptr_range().contains(&torrent.info.get().as_ptr()));
+        assert!(ser::to_bytes(&torrent).unwrap() == input);
+
+        // Readers can only fill an owned one.
+        let raw = de::from_reader::<_, Box<RawValue>>(&b"l3:abci-1ee"[..]).unwrap();
+        assert!(raw.get() == b"l3:abci-1ee");
+        let raw = de::from_bytes::<Vec<Box<RawValue>>>(b"l1:ald1:bi1eeee").unwrap();
+        assert!(raw[0].get() == b"1:a" && raw[1].get() == b"ld1:bi1eee");
+
+        // The value is still validated.
+        let err = de::from_bytes::<Box<RawValue>>(b"li01ee").unwrap_err();
+        assert!(err.kind() == &ErrorKind::LeadingZero);
+        let err = RawValue::from_bytes(b"i1ei2e".to_vec()).unwrap_err();
+        assert!(err.kind() == &ErrorKind::TrailingCharacters);
+        let err = RawValue::from_bytes(b"d1:ae".to_vec()).unwrap_err();
+        assert!(err.kind() == &ErrorKind::Syntax);
+        let err = RawValue::from_bytes(b"di1ei2ee".to_vec()).unwrap_err();
+        assert!(err.kind() == &ErrorKind::KeyMustBeAString);
+        let err = de::from_bytes::<TestTorrentRaw>(b"d8:announce3:url4:infod1:aee").unwrap_err();
+        assert!(err.kind() == &ErrorKind::Syntax && err.path() == Some("info"));
+        let err = de::from_reader::<_, Box<RawValue>>(&b"ldi1ei2eee"[..]).unwrap_err();
+        assert!(err.kind() == &ErrorKind::KeyMustBeAString);
+
+        // Going through `Value` decodes it, and a `Value` gives its canonical
+        // encoding.
+        let raw = RawValue::from_bytes(b"d1:bi2e1:ai1ee".to_vec()).unwrap();
+        let v = value::to_value(&raw).unwrap();
+        assert!(v.as_dict().unwrap().len() == 2);
+        assert!(value::from_value::<Box<RawValue>>(v).unwrap().get() == b"d1:ai1e1:bi2ee");
+    }
 }
@@ -0,0 +1,283 @@
+use std::io;
+use std::ops::Deref;
+
+use crate::error::{Error, ErrorKind, Result};
+
+// Trait used by the deserializer for iterating over input. This is manually
+// "specialized" for iterating over `&[u8]` and `io::Read`, the same way
+// serde_json does it.
+//
+// This trait is sealed and cannot be implemented for types outside of
+// serde_bencode.
+pub trait Read<'de>: private::Sealed {
+    // Whether `parse_bytes` copies into the scratch buffer rather than
+    // borrowing.
+    #[doc(hidden)]
+    const COPIES: bool;
+
+    // Look at the next byte without consuming it. `None` at end of input.
+    #[doc(hidden)]
+    fn peek(&mut self) -> Result<Option<u8>>;
+
+    // Consume the byte that was just peeked at.
+    #[doc(hidden)]
+    fn discard(&mut self);
+
+    // Read exactly `len` bytes. The result is borrowed from the input when
+    // possible and copied into `scratch` otherwise.
+    #[doc(hidden)]
+    fn parse_bytes<'s>(&'s mut self, len: usize,
+                       scratch: &'s mut Vec<u8>) -> Result<Reference<'de, 's, [u8]>>;
+
+    // Skip over exactly `len` bytes without keeping them.
+    #[doc(hidden)]
+    fn ignore_bytes(&mut self, len: usize) -> Result<()>;
+
+    // Number of bytes consumed so far. A byte that has only been peeked at
+    // doesn't count.
+    #[doc(hidden)]
+    fn byte_offset(&self) -> usize;
+
+    // Start recording the bytes consumed from here on, for `RawValue`.
+    #[doc(hidden)]
+    fn begin_raw_buffering(&mut self);
+
+    // Stop recording and return everything consumed since the matching
+    // `begin_raw_buffering`, borrowed from the input when possible.
+    #[doc(hidden)]
+    fn end_raw_buffering<'s>(&'s mut self,
+                             scratch: &'s mut Vec<u8>) -> Reference<'de, 's, [u8]>;
+}
+
+// Byte strings handed out by `Read::parse_bytes`: either borrowed for the
+// whole `'de` lifetime, or only valid until the scratch buffer is reused.
+pub enum Reference<'b, 'c, T>
+where
+    T: ?Sized + 'static,
+{
+    Borrowed(&'b T),
+    Copied(&'c T),
+}
+
+impl<'b, 'c, T> Deref for Reference<'b, 'c, T>
+where
+    T: ?Sized + 'static,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        match *self {
+            Reference::Borrowed(b) => b,
+            Reference::Copied(c) => c,
+        }
+    }
+}
+
+// Bencode input source that reads from a slice of bytes. Byte strings are
+// borrowed straight out of the slice.
+pub struct SliceRead<'a> {
+    slice: &'a [u8],
+    // Index of the next byte to be read.
+    index: usize,
+    // Where the raw value being recorded starts.
+    raw_start: usize,
+}
+
+impl<'a> SliceRead<'a> {
+    pub fn new(slice: &'a [u8]) -> Self {
+        SliceRead {
+            slice,
+            index: 0,
+            raw_start: 0,
+        }
+    }
+}
+
+impl<'a> private::Sealed for SliceRead<'a> {}
+
+impl<'a> Read<'a> for SliceRead<'a> {
+    const COPIES: bool = false;
+
+    fn peek(&mut self) -> Result<Option<u8>> {
+        Ok(self.slice.get(self.index).copied())
+    }
+
+    fn discard(&mut self) {
+        self.index += 1;
+    }
+
+    fn parse_bytes<'s>(&'s mut self, len: usize,
+                       _scratch: &'s mut Vec<u8>) -> Result<Reference<'a, 's, [u8]>> {
+        // The declared length comes straight from the input, so it can't be
+        // trusted to fit in what is left of it.
+        if len > self.slice.len() - self.index {
+            return Err(Error::at(ErrorKind::Eof, self.slice.len()));
+        }
+
+        let bytes = &self.slice[self.index..self.index + len];
+        self.index += len;
+        Ok(Reference::Borrowed(bytes))
+    }
+
+    fn ignore_bytes(&mut self, len: usize) -> Result<()> {
+        if len > self.slice.len() - self.index {
+            return Err(Error::at(ErrorKind::Eof, self.slice.len()));
+        }
+
+        self.index += len;
+        Ok(())
+    }
+
+    fn byte_offset(&self) -> usize {
+        self.index
+    }
+
+    fn begin_raw_buffering(&mut self) {
+        self.raw_start = self.index;
+    }
+
+    fn end_raw_buffering<'s>(&'s mut self,
+                             _scratch: &'s mut Vec<u8>) -> Reference<'a, 's, [u8]> {
+        Reference::Borrowed(&self.slice[self.raw_start..self.index])
+    }
+}
+
+// Bencode input source that reads from a `std::io::Read`. Nothing can be
+// borrowed from a stream, so byte strings are always copied.
+//
+// Bytes are pulled from the reader one at a time, so an unbuffered source
+// such as a `File` should be wrapped in an `io::BufReader`.
+pub struct IoRead<R>
+where
+    R: io::Read,
+{
+    reader: R,
+    // One byte of lookahead, filled in by `peek`.
+    peeked: Option<u8>,
+    // Bytes consumed so far, including the ones skipped or copied out in
+    // bulk.
+    offset: usize,
+    // While a raw value is being recorded, every consumed byte is copied
+    // here.
+    raw_buffer: Option<Vec<u8>>,
+}
+
+impl<R> IoRead<R>
+where
+    R: io::Read,
+{
+    pub fn new(reader: R) -> Self {
+        IoRead {
+            reader,
+            peeked: None,
+            offset: 0,
+            raw_buffer: None,
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        let mut buf = [0];
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buf[0])),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(Error::at(ErrorKind::Io(err), self.offset)),
+            }
+        }
+    }
+}
+
+impl<R> private::Sealed for IoRead<R> where R: io::Read {}
+
+impl<'de, R> Read<'de> for IoRead<R>
+where
+    R: io::Read,
+{
+    const COPIES: bool = true;
+
+    fn peek(&mut self) -> Result<Option<u8>> {
+        if self.peeked.is_none() {
+            self.peeked = self.read_byte()?;
+        }
+        Ok(self.peeked)
+    }
+
+    fn discard(&mut self) {
+        if let Some(ch) = self.peeked.take() {
+            self.offset += 1;
+            if let Some(ref mut buf) = self.raw_buffer {
+                buf.push(ch);
+            }
+        }
+    }
+
+    fn parse_bytes<'s>(&'s mut self, len: usize,
+                       scratch: &'s mut Vec<u8>) -> Result<Reference<'de, 's, [u8]>> {
+        scratch.clear();
+
+        let mut len = len;
+        if len > 0 {
+            if let Some(ch) = self.peeked.take() {
+                scratch.push(ch);
+                len -= 1;
+            }
+        }
+
+        // Reading through `take` means a bogus length prefix can't cause a
+        // huge allocation up front: the buffer only grows as data arrives.
+        let mut reader = io::Read::take(&mut self.reader, len as u64);
+        let read = io::Read::read_to_end(&mut reader, scratch);
+        self.offset += scratch.len();
+        if let Some(ref mut buf) = self.raw_buffer {
+            buf.extend_from_slice(scratch);
+        }
+        let read = read.map_err(|err| Error::at(ErrorKind::Io(err), self.offset))?;
+        if read < len {
+            return Err(Error::at(ErrorKind::Eof, self.offset));
+        }
+
+        Ok(Reference::Copied(scratch))
+    }
+
+    fn ignore_bytes(&mut self, len: usize) -> Result<()> {
+        let mut len = len;
+        if len > 0 && self.peeked.is_some() {
+            self.discard();
+            len -= 1;
+        }
+
+        // Skipped bytes still have to be kept when they are part of a raw
+        // value.
+        let mut reader = io::Read::take(&mut self.reader, len as u64);
+        let skipped = match self.raw_buffer {
+            Some(ref mut buf) => io::copy(&mut reader, buf),
+            None => io::copy(&mut reader, &mut io::sink()),
+        }
+        .map_err(|err| Error::at(ErrorKind::Io(err), self.offset))?;
+        self.offset += skipped as usize;
+        if skipped < len as u64 {
+            return Err(Error::at(ErrorKind::Eof, self.offset));
+        }
+
+        Ok(())
+    }
+
+    fn byte_offset(&self) -> usize {
+        self.offset
+    }
+
+    fn begin_raw_buffering(&mut self) {
+        self.raw_buffer = Some(Vec::new());
+    }
+
+    fn end_raw_buffering<'s>(&'s mut self,
+                             scratch: &'s mut Vec<u8>) -> Reference<'de, 's, [u8]> {
+        *scratch = self.raw_buffer.take().unwrap_or_default();
+        Reference::Copied(scratch)
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+}
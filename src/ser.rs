@@ -1,29 +1,168 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::mem;
+use std::ops::Range;
+
 use serde::{ser, Serialize};
 
-use crate::error::{Error, Result};
+use crate::config::Config;
+use crate::error::{Error, ErrorKind, Result};
+use crate::value::RAW_VALUE_TOKEN;
+
+// Bencode is appended to `writer` as values are serialized. Byte strings may
+// hold arbitrary binary data, so the output is not guaranteed to be valid
+// UTF-8. Without a type parameter this is a serializer into a `Vec<u8>`.
+pub struct Serializer<W = Vec<u8>> {
+    writer: W,
+    config: Config,
+    // How many more lists and dictionaries may be opened before hitting the
+    // configured recursion limit.
+    remaining_depth: usize,
+    // Set while serializing a dictionary value, where `None` is allowed and
+    // writes nothing. Opening a container clears it, since `None` has no
+    // representation inside one.
+    omit_none: bool,
+    // Output is collected here instead of going to `writer` while any
+    // dictionary is open, see `SerializeDict`.
+    buffer: Vec<u8>,
+    open_dicts: usize,
+    // Every dictionary finished so far within `buffer`, keyed by where it
+    // starts.
+    dicts: BTreeMap<usize, Dict>,
+}
+
+impl Serializer {
+    pub fn new() -> Self {
+        Serializer::with_capacity(0)
+    }
 
-pub struct Serializer {
-    // This string starts empty and JSON is appended as values are serialized.
-    output: String,
+    pub fn with_capacity(capacity: usize) -> Self {
+        Serializer::from_writer(Vec::with_capacity(capacity))
+    }
+}
+
+impl<W> Serializer<W>
+where
+    W: io::Write,
+{
+    // Write straight to any `io::Write`, e.g. a `BufWriter<File>` or a
+    // `TcpStream`. Small writes are issued for every token, so unbuffered
+    // writers should be wrapped in an `io::BufWriter`.
+    pub fn from_writer(writer: W) -> Self {
+        let config = Config::default();
+        Serializer {
+            writer,
+            config,
+            remaining_depth: config.recursion_limit,
+            omit_none: false,
+            buffer: Vec::new(),
+            open_dicts: 0,
+            dicts: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self.remaining_depth = config.recursion_limit;
+        self
+    }
+
+    // Consume the serializer, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.open_dicts > 0 {
+            self.buffer.extend_from_slice(bytes);
+            Ok(())
+        } else {
+            self.writer.write_all(bytes).map_err(Error::from)
+        }
+    }
+
+    // Called once the outermost open dictionary is complete to write out
+    // everything buffered since it was opened, with all dictionaries sorted.
+    fn flush_dicts(&mut self) -> Result<()> {
+        let mut buffer = mem::take(&mut self.buffer);
+        let dicts = mem::take(&mut self.dicts);
+        write_sorted(&mut self.writer, &buffer, &dicts, 0..buffer.len())?;
+        // Keep the allocation for the next dictionary.
+        buffer.clear();
+        self.buffer = buffer;
+        Ok(())
+    }
+
+    // Called before writing the opening byte of a list or dictionary, and
+    // paired with `leave_container` once it is closed.
+    fn enter_container(&mut self) -> Result<()> {
+        if self.remaining_depth == 0 {
+            return Err(ErrorKind::RecursionLimitExceeded.into());
+        }
+        self.remaining_depth -= 1;
+        self.omit_none = false;
+        Ok(())
+    }
+
+    fn leave_container(&mut self) {
+        self.remaining_depth += 1;
+    }
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Serializer::new()
+    }
 }
 
 // By convention, the public API of a Serde serializer is one or more `to_abc`
 // functions such as `to_string`, `to_bytes`, or `to_writer` depending on what
 // Rust types the serializer is able to produce as output.
 //
-// This basic serializer supports only `to_string`.
-pub fn to_str<T>(value: &T) -> Result<String>
+// Bencode is a binary format, so `to_bytes` is the primary entry point.
+pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
 where
-    T: Serialize,
+    T: ?Sized + Serialize,
 {
-    let mut serializer = Serializer {
-        output: String::new(),
-    };
+    let mut serializer = Serializer::new();
     value.serialize(&mut serializer)?;
-    Ok(serializer.output)
+    Ok(serializer.into_inner())
+}
+
+// Serialize straight into an IO stream. Lists, byte strings and integers are
+// written as they are serialized, but a dictionary can only be written out
+// once all of its entries are known, because they must be sorted. Any
+// dictionary that is not nested in another one, along with everything inside
+// it, is therefore buffered in memory in full before it reaches `writer`.
+// Torrent and resume files are a single top-level dictionary, so for them the
+// whole document is buffered, and this only saves the final copy compared to
+// `to_bytes`.
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: io::Write,
+    T: ?Sized + Serialize,
+{
+    let mut serializer = Serializer::from_writer(writer);
+    value.serialize(&mut serializer)
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+// Alias of `to_bytes` for those used to the `serde_json` naming.
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    to_bytes(value)
+}
+
+// Only succeeds if every byte string in the output is valid UTF-8.
+pub fn to_string<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    String::from_utf8(to_bytes(value)?).map_err(|_| Error::from(ErrorKind::InvalidUtf8))
+}
+
+impl<'a, W: io::Write> ser::Serializer for &'a mut Serializer<W> {
     // The output type produced by this `Serializer` during successful
     // serialization. Most serializers that produce text or binary output should
     // set `Ok = ()` and serialize into an `io::Write` or buffer contained
@@ -36,20 +175,23 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type Error = Error;
 
     // Associated types for keeping track of additional state while serializing
-    // compound data structures like sequences and maps. In this case no
-    // additional state is required beyond what is already stored in the
-    // Serializer struct.
+    // compound data structures like sequences and maps. Lists need no state
+    // beyond what is already stored in the Serializer struct, but dictionaries
+    // have to buffer their entries so they can be written out sorted by key.
     type SerializeSeq = Self;
     type SerializeTuple = Self;
     type SerializeTupleStruct = Self;
     type SerializeTupleVariant = Self;
-    type SerializeMap = Self;
-    type SerializeStruct = Self;
-    type SerializeStructVariant = Self;
+    type SerializeMap = SerializeDict<'a, W>;
+    type SerializeStruct = SerializeDict<'a, W>;
+    type SerializeStructVariant = SerializeDict<'a, W>;
 
-    fn serialize_bool(self, _v: bool) -> Result<()>
+    fn serialize_bool(self, v: bool) -> Result<()>
 	{
-		unimplemented!()
+		if !self.config.bools_as_integers {
+			return Err(ErrorKind::BoolUnsupported.into());
+		}
+		self.serialize_u8(u8::from(v))
 	}
 
 	fn serialize_i8(self, v: i8) -> Result<()>
@@ -69,136 +211,187 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
 	fn serialize_i64(self, v: i64) -> Result<()>
 	{
-        self.output += "i";
-		self.output += &v.to_string();
-        self.output += "e";
+		self.serialize_i128(i128::from(v))
+	}
+
+	// Bencode integers have no fixed width, so the widest types can be
+	// written out as they are.
+	fn serialize_i128(self, v: i128) -> Result<()>
+	{
+        self.write(b"i")?;
+		self.write(v.to_string().as_bytes())?;
+        self.write(b"e")?;
         Ok(())
 	}
 
-	fn serialize_u8(self, _v: u8) -> Result<()>
+	fn serialize_u8(self, v: u8) -> Result<()>
 	{
-		unimplemented!()
+		self.serialize_u128(u128::from(v))
 	}
 
-	fn serialize_u16(self, _v: u16) -> Result<()>
+	fn serialize_u16(self, v: u16) -> Result<()>
 	{
-		unimplemented!()
+		self.serialize_u128(u128::from(v))
 	}
 
-	fn serialize_u32(self, _v: u32) -> Result<()>
+	fn serialize_u32(self, v: u32) -> Result<()>
 	{
-		unimplemented!()
+		self.serialize_u128(u128::from(v))
 	}
 
-	fn serialize_u64(self, _v: u64) -> Result<()>
+	fn serialize_u64(self, v: u64) -> Result<()>
 	{
-		unimplemented!()
+		self.serialize_u128(u128::from(v))
+	}
+
+	fn serialize_u128(self, v: u128) -> Result<()>
+	{
+        self.write(b"i")?;
+		self.write(v.to_string().as_bytes())?;
+        self.write(b"e")?;
+        Ok(())
 	}
 
 	fn serialize_f32(self, _v: f32) -> Result<()>
 	{
-		unimplemented!()
+		Err(ErrorKind::FloatUnsupported.into())
 	}
 
 	fn serialize_f64(self, _v: f64) -> Result<()>
 	{
-		unimplemented!()
+		Err(ErrorKind::FloatUnsupported.into())
 	}
 
-	fn serialize_char(self, _v: char) -> Result<()>
+	// A `char` is a byte string holding its UTF-8 encoding.
+	fn serialize_char(self, v: char) -> Result<()>
 	{
-		unimplemented!()
+		let mut buf = [0; 4];
+		self.serialize_str(v.encode_utf8(&mut buf))
 	}
 
 	fn serialize_str(self, v: &str) -> Result<()>
 	{
-        self.output += &v.len().to_string();
-        self.output += ":";
-        self.output += v;
-		Ok(())
+		self.serialize_bytes(v.as_bytes())
 	}
 
-	fn serialize_bytes(self, _v: &[u8]) -> Result<()>
+	fn serialize_bytes(self, v: &[u8]) -> Result<()>
 	{
-		unimplemented!()
+        self.write(v.len().to_string().as_bytes())?;
+        self.write(b":")?;
+        self.write(v)?;
+		Ok(())
 	}
 
+	// Bencode has no null. `None` dictionary values are left out entirely
+	// (see `SerializeDict`), anywhere else there is no way to represent them.
 	fn serialize_none(self) -> Result<()>
 	{
-		unimplemented!()
+		if self.omit_none {
+			return Ok(());
+		}
+		Err(ErrorKind::UnexpectedNone.into())
 	}
 
-	fn serialize_some<T>(self, _value: &T) -> Result<()>
+	fn serialize_some<T>(self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
 	{
-		unimplemented!()
+		value.serialize(self)
 	}
 
+	// There is no null in bencode; `()` is written as an empty list, which
+	// reads back unambiguously as long as the target type is known.
 	fn serialize_unit(self) -> Result<()>
 	{
-		unimplemented!()
+		self.write(b"le")?;
+		Ok(())
 	}
 
+	// Unit struct means a named value containing no data. Again, since there
+	// is no data, map this to the same representation as `()`.
 	fn serialize_unit_struct(self, _name: &'static str) -> Result<()>
 	{
-		unimplemented!()
+		self.serialize_unit()
 	}
 
+	// Enums are externally tagged. A unit variant is just its name, every
+	// other kind of variant is a single-entry dictionary mapping the name to
+	// the variant's contents.
 	fn serialize_unit_variant(self, _name: &'static str,
                               _variant_index: u32,
-                              _variant: &'static str,) -> Result<()>
+                              variant: &'static str,) -> Result<()>
 	{
-		unimplemented!()
+		self.serialize_str(variant)
 	}
 
-	fn serialize_newtype_struct<T>(self, _name: &'static str,
-                                    _value: &T) -> Result<()>
+	// As is done here, serializers are encouraged to treat newtype structs as
+	// insignificant wrappers around the data they contain. The exception is
+	// `RawValue`, whose bytes are already encoded and are written as they are.
+	fn serialize_newtype_struct<T>(self, name: &'static str,
+                                    value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
 	{
-		unimplemented!()
+		if name == RAW_VALUE_TOKEN {
+			let raw = value.serialize(MapKeySerializer)?;
+			return self.write(&raw);
+		}
+		value.serialize(self)
 	}
 
 	fn serialize_newtype_variant<T>(self, _name: &'static str,
                                 _variant_index: u32,
-                                _variant: &'static str,
-                                _value: &T,) -> Result<()>
+                                variant: &'static str,
+                                value: &T,) -> Result<()>
     where
         T: ?Sized + Serialize,
 	{
-		unimplemented!()
+		self.enter_container()?;
+		self.write(b"d")?;
+		variant.serialize(&mut *self)?;
+		value.serialize(&mut *self)?;
+		self.write(b"e")?;
+		self.leave_container();
+		Ok(())
 	}
 
 	fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq>
 	{
-		self.output += "l";
+		self.enter_container()?;
+		self.write(b"l")?;
         Ok(self)
 	}
 
-	fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple>
+	// Tuples, tuple structs and fixed-size arrays are all plain lists; the
+	// length is implied by the type and checked again when deserializing.
+	fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple>
 	{
-		unimplemented!()
+		self.serialize_seq(Some(len))
 	}
 
 	fn serialize_tuple_struct(self, _name: &'static str,
-                              _len: usize,) -> Result<Self::SerializeTupleStruct>
+                              len: usize,) -> Result<Self::SerializeTupleStruct>
 	{
-		unimplemented!()
+		self.serialize_seq(Some(len))
 	}
 
 	fn serialize_tuple_variant(self, _name: &'static str,
                                 _variant_index: u32,
-                                _variant: &'static str,
+                                variant: &'static str,
                                 _len: usize,) -> Result<Self::SerializeTupleVariant>
 	{
-		unimplemented!()
+		self.enter_container()?;
+		self.write(b"d")?;
+		variant.serialize(&mut *self)?;
+		self.enter_container()?;
+		self.write(b"l")?;
+		Ok(self)
 	}
 
 	fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap>
 	{
-		self.output += "d";
-        Ok(self)
+		self.enter_container()?;
+		Ok(SerializeDict::new(self))
 	}
 
 	fn serialize_struct(self, _name: &'static str, 
@@ -209,10 +402,16 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
 	fn serialize_struct_variant(self, _name: &'static str,
                                 _variant_index: u32,
-                                _variant: &'static str,
+                                variant: &'static str,
                                 _len: usize,) -> Result<Self::SerializeStructVariant>
 	{
-		unimplemented!()
+		self.enter_container()?;
+		self.write(b"d")?;
+		variant.serialize(&mut *self)?;
+		self.enter_container()?;
+		let mut dict = SerializeDict::new(self);
+		dict.variant = true;
+		Ok(dict)
 	}
 }
 
@@ -223,7 +422,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 //
 // This impl is SerializeSeq so these methods are called after `serialize_seq`
 // is called on the Serializer.
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl<W: io::Write> ser::SerializeSeq for &mut Serializer<W> {
     // Must match the `Ok` type of the serializer.
     type Ok = ();
     // Must match the `Error` type of the serializer.
@@ -239,85 +438,183 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
 
     // Close the sequence.
     fn end(self) -> Result<()> {
-        self.output += "e";
+        self.write(b"e")?;
+        self.leave_container();
         Ok(())
     }
 }
 
 // Same thing but for tuples.
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl<W: io::Write> ser::SerializeTuple for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T>(&mut self, _value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        // if !self.output.ends_with('[') {
-        //     self.output += ",";
-        // }
-        // value.serialize(&mut **self)
-        unimplemented!()
+        value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<()> {
-        // self.output += "]";
-        // Ok(())
-        unimplemented!()
+        self.write(b"e")?;
+        self.leave_container();
+        Ok(())
     }
 }
 
 // Same thing but for tuple structs.
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl<W: io::Write> ser::SerializeTupleStruct for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        // if !self.output.ends_with('[') {
-        //     self.output += ",";
-        // }
-        // value.serialize(&mut **self)
-        unimplemented!()
+        value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<()> {
-        // self.output += "]";
-        // Ok(())
-        unimplemented!()
+        self.write(b"e")?;
+        self.leave_container();
+        Ok(())
     }
 }
 
 // Tuple variants are a little different. Refer back to the
 // `serialize_tuple_variant` method above:
 //
-//    self.output += "{";
+//    self.write(b"d")?;
 //    variant.serialize(&mut *self)?;
-//    self.output += ":[";
+//    self.write(b"l")?;
 //
-// So the `end` method in this impl is responsible for closing both the `]` and
-// the `}`.
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+// So the `end` method in this impl is responsible for closing both the list
+// and the dictionary.
+impl<W: io::Write> ser::SerializeTupleVariant for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        // if !self.output.ends_with('[') {
-        //     self.output += ",";
-        // }
-        // value.serialize(&mut **self)
-        unimplemented!()
+        value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<()> {
-        // self.output += "]}";
-        // Ok(())
-        unimplemented!()
+        self.write(b"ee")?;
+        self.leave_container();
+        self.leave_container();
+        Ok(())
+    }
+}
+
+// A finished dictionary within `Serializer::buffer`: its entries, sorted by
+// key, and where in the buffer each value was serialized.
+struct Dict {
+    end: usize,
+    entries: Vec<(Vec<u8>, Range<usize>)>,
+}
+
+// Write `range` of `buffer`, replacing every dictionary in it with its sorted
+// form. Nesting is bounded by the recursion limit.
+fn write_sorted<W>(writer: &mut W, buffer: &[u8], dicts: &BTreeMap<usize, Dict>,
+                   range: Range<usize>) -> io::Result<()>
+where
+    W: io::Write,
+{
+    let mut pos = range.start;
+    // Dictionaries nested deeper start after the one containing them, so the
+    // first one found is always a direct child.
+    while let Some((&start, dict)) = dicts.range(pos..range.end).next() {
+        writer.write_all(&buffer[pos..start])?;
+        writer.write_all(b"d")?;
+        for (key, value) in &dict.entries {
+            write!(writer, "{}:", key.len())?;
+            writer.write_all(key)?;
+            write_sorted(writer, buffer, dicts, value.clone())?;
+        }
+        writer.write_all(b"e")?;
+        pos = dict.end;
+    }
+    writer.write_all(&buffer[pos..range.end])
+}
+
+// Bencode requires dictionary keys to appear sorted by their raw bytes, but
+// serde hands out map entries and struct fields in whatever order the type
+// stores them. Entries are therefore serialized in the order they come into
+// the serializer's buffer, shared by all dictionaries nested in the outermost
+// one, and only their positions are sorted. Once the outermost dictionary is
+// complete the buffer is written out in one go, following the sorted order.
+pub struct SerializeDict<'a, W: 'a> {
+    ser: &'a mut Serializer<W>,
+    // Where this dictionary starts in the buffer.
+    start: usize,
+    entries: Vec<(Vec<u8>, Range<usize>)>,
+    next_key: Option<Vec<u8>>,
+    // Set for struct variants, whose fields are nested inside the dictionary
+    // holding the variant name.
+    variant: bool,
+}
+
+impl<'a, W: io::Write> SerializeDict<'a, W> {
+    fn new(ser: &'a mut Serializer<W>) -> Self {
+        ser.open_dicts += 1;
+        let start = ser.buffer.len();
+        // Not written out as is, but it makes sure no two dictionaries start
+        // at the same position, even empty ones.
+        ser.buffer.push(b'd');
+        SerializeDict {
+            ser,
+            start,
+            entries: Vec::new(),
+            next_key: None,
+            variant: false,
+        }
+    }
+
+    fn push_entry<T>(&mut self, key: Vec<u8>, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let start = self.ser.buffer.len();
+        self.ser.omit_none = true;
+        let result = value.serialize(&mut *self.ser);
+        self.ser.omit_none = false;
+        result?;
+        // Only `None` writes nothing, and an absent key is how a dictionary
+        // says `None`.
+        let end = self.ser.buffer.len();
+        if end > start {
+            self.entries.push((key, start..end));
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        // Stable, so of two equal keys the first one serialized stays first.
+        self.entries.sort_by(|a, b| a.0.cmp(&b.0));
+        if self.entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(ErrorKind::DuplicateKey.into());
+        }
+
+        let ser = &mut *self.ser;
+        let dict = Dict {
+            end: ser.buffer.len(),
+            entries: self.entries,
+        };
+        ser.dicts.insert(self.start, dict);
+        ser.open_dicts -= 1;
+        if ser.open_dicts == 0 {
+            ser.flush_dicts()?;
+        }
+        ser.leave_container();
+        if self.variant {
+            ser.write(b"e")?;
+            ser.leave_container();
+        }
+        Ok(())
     }
 }
 
@@ -327,53 +624,42 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
 //
 // There is a third optional method on the `SerializeMap` trait. The
 // `serialize_entry` method allows serializers to optimize for the case where
-// key and value are both available simultaneously. In JSON it doesn't make a
+// key and value are both available simultaneously. In bencode it doesn't make a
 // difference so the default behavior for `serialize_entry` is fine.
-impl<'a> ser::SerializeMap for &'a mut Serializer {
+impl<'a, W: io::Write> ser::SerializeMap for SerializeDict<'a, W> {
     type Ok = ();
     type Error = Error;
 
-    // The Serde data model allows map keys to be any serializable type. JSON
-    // only allows string keys so the implementation below will produce invalid
-    // JSON if the key serializes as something other than a string.
-    //
-    // A real JSON serializer would need to validate that map keys are strings.
-    // This can be done by using a different Serializer to serialize the key
-    // (instead of `&mut **self`) and having that other serializer only
-    // implement `serialize_str` and return an error on any other data type.
-    fn serialize_key<T>(&mut self, _key: &T) -> Result<()>
+    // The Serde data model allows map keys to be any serializable type.
+    // Bencode only allows byte string keys, so keys go through a dedicated
+    // serializer that captures the raw bytes and rejects everything else.
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        // if !self.output.ends_with('{') {
-        //     self.output += ",";
-        // }
-        // key.serialize(&mut **self)
-        unimplemented!()
+        self.next_key = Some(key.serialize(MapKeySerializer)?);
+        Ok(())
     }
 
-    // It doesn't make a difference whether the colon is printed at the end of
-    // `serialize_key` or at the beginning of `serialize_value`. In this case
-    // the code is a bit simpler having it here.
-    fn serialize_value<T>(&mut self, _value: &T) -> Result<()>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        // self.output += ":";
-        // value.serialize(&mut **self)
-        unimplemented!()
+        let key = self.next_key.take();
+        // Panic because this indicates a bug in the program rather than an
+        // expected failure.
+        let key = key.expect("serialize_value called before serialize_key");
+        self.push_entry(key, value)
     }
 
     fn end(self) -> Result<()> {
-        // self.output += "}";
-        // Ok(())
-        unimplemented!()
+        self.finish()
     }
 }
 
 // Structs are like maps in which the keys are constrained to be compile-time
 // constant strings.
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl<'a, W: io::Write> ser::SerializeStruct for SerializeDict<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -381,38 +667,192 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        key.serialize(&mut **self)?;
-        value.serialize(&mut **self)
+        self.push_entry(key.as_bytes().to_vec(), value)
     }
 
     fn end(self) -> Result<()> {
-        self.output += "e";
-        Ok(())
+        self.finish()
     }
 }
 
 // Similar to `SerializeTupleVariant`, here the `end` method is responsible for
-// closing both of the curly braces opened by `serialize_struct_variant`.
-impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+// closing both of the dictionaries opened by `serialize_struct_variant`.
+impl<'a, W: io::Write> ser::SerializeStructVariant for SerializeDict<'a, W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _key: &'static str, _value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        // if !self.output.ends_with('{') {
-        //     self.output += ",";
-        // }
-        // key.serialize(&mut **self)?;
-        // self.output += ":";
-        // value.serialize(&mut **self)
-        unimplemented!()
+        self.push_entry(key.as_bytes().to_vec(), value)
     }
 
     fn end(self) -> Result<()> {
-        // self.output += "}}";
-        // Ok(())
-        unimplemented!()
+        self.finish()
+    }
+}
+
+// Serializes a map key to its raw bytes. Only byte strings can be dictionary
+// keys in bencode, so anything that isn't naturally a string (integers,
+// sequences, maps, ...) is rejected rather than silently stringified.
+pub(crate) struct MapKeySerializer;
+
+fn key_must_be_a_string() -> Error {
+    ErrorKind::KeyMustBeAString.into()
+}
+
+impl ser::Serializer for MapKeySerializer {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    type SerializeSeq = ser::Impossible<Vec<u8>, Error>;
+    type SerializeTuple = ser::Impossible<Vec<u8>, Error>;
+    type SerializeTupleStruct = ser::Impossible<Vec<u8>, Error>;
+    type SerializeTupleVariant = ser::Impossible<Vec<u8>, Error>;
+    type SerializeMap = ser::Impossible<Vec<u8>, Error>;
+    type SerializeStruct = ser::Impossible<Vec<u8>, Error>;
+    type SerializeStructVariant = ser::Impossible<Vec<u8>, Error>;
+
+    fn serialize_str(self, v: &str) -> Result<Vec<u8>> {
+        Ok(v.as_bytes().to_vec())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Vec<u8>> {
+        Ok(v.to_vec())
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Vec<u8>> {
+        let mut buf = [0; 4];
+        Ok(v.encode_utf8(&mut buf).as_bytes().to_vec())
+    }
+
+    fn serialize_none(self) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit(self) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Vec<u8>> {
+        Err(key_must_be_a_string())
+    }
+
+    // Fieldless enums used as keys are written as their variant name.
+    fn serialize_unit_variant(self, _name: &'static str,
+                              _variant_index: u32,
+                              variant: &'static str) -> Result<Vec<u8>> {
+        Ok(variant.as_bytes().to_vec())
+    }
+
+    // Newtypes such as `struct PeerId(String)` are keys if their contents are.
+    // A `RawValue` is an encoded value rather than a byte string, so it isn't.
+    fn serialize_newtype_struct<T>(self, name: &'static str,
+                                   value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        if name == RAW_VALUE_TOKEN {
+            return Err(key_must_be_a_string());
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(self, _name: &'static str,
+                                    _variant_index: u32,
+                                    _variant: &'static str,
+                                    _value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str,
+                              _len: usize) -> Result<Self::SerializeTupleStruct> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str,
+                               _variant_index: u32,
+                               _variant: &'static str,
+                               _len: usize) -> Result<Self::SerializeTupleVariant> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct(self, _name: &'static str,
+                        _len: usize) -> Result<Self::SerializeStruct> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str,
+                                _variant_index: u32,
+                                _variant: &'static str,
+                                _len: usize) -> Result<Self::SerializeStructVariant> {
+        Err(key_must_be_a_string())
     }
 }
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str;
+
+use serde::de::{DeserializeOwned, Error as _, MapAccess, SeqAccess, Unexpected, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::Result;
+
+mod de;
+mod raw;
+mod ser;
+
+pub use self::raw::RawValue;
+pub(crate) use self::raw::RAW_VALUE_TOKEN;
+
+// Any valid bencode document. Useful when the shape of the input isn't known
+// at compile time, in the same way as `serde_json::Value`.
+//
+// Dictionary keys are kept in a `BTreeMap` so they are always iterated (and
+// serialized) in the raw byte order bencode requires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Dict(BTreeMap<Vec<u8>, Value>),
+}
+
+impl Value {
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Value::Int(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::Bytes(ref bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    // Only returns `Some` if the byte string is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes().and_then(|bytes| str::from_utf8(bytes).ok())
+    }
+
+    pub fn as_list(&self) -> Option<&Vec<Value>> {
+        match *self {
+            Value::List(ref list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&BTreeMap<Vec<u8>, Value>> {
+        match *self {
+            Value::Dict(ref dict) => Some(dict),
+            _ => None,
+        }
+    }
+}
+
+// Serializes a dictionary key (or the bytes of a `RawValue`) as a byte string
+// rather than as a sequence of integers, which is what `Vec<u8>` would do on
+// its own.
+struct KeyBytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for KeyBytes<'a> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self {
+            Value::Int(i) => serializer.serialize_i64(i),
+            Value::Bytes(ref bytes) => serializer.serialize_bytes(bytes),
+            Value::List(ref list) => serializer.collect_seq(list),
+            Value::Dict(ref dict) => {
+                serializer.collect_map(dict.iter().map(|(k, v)| (KeyBytes(k), v)))
+            }
+        }
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any valid bencode value")
+    }
+
+    fn visit_i64<E>(self, v: i64) -> std::result::Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> std::result::Result<Value, E>
+    where
+        E: serde::de::Error,
+    {
+        if v <= i64::MAX as u64 {
+            Ok(Value::Int(v as i64))
+        } else {
+            Err(E::invalid_value(Unexpected::Unsigned(v), &self))
+        }
+    }
+
+    fn visit_i128<E>(self, v: i128) -> std::result::Result<Value, E>
+    where
+        E: serde::de::Error,
+    {
+        match i64::try_from(v) {
+            Ok(v) => Ok(Value::Int(v)),
+            Err(_) => Err(E::invalid_value(Unexpected::Other("128-bit integer"), &self)),
+        }
+    }
+
+    fn visit_u128<E>(self, v: u128) -> std::result::Result<Value, E>
+    where
+        E: serde::de::Error,
+    {
+        match i64::try_from(v) {
+            Ok(v) => Ok(Value::Int(v)),
+            Err(_) => Err(E::invalid_value(Unexpected::Other("128-bit integer"), &self)),
+        }
+    }
+
+    fn visit_str<E>(self, v: &str) -> std::result::Result<Value, E> {
+        Ok(Value::Bytes(v.as_bytes().to_vec()))
+    }
+
+    fn visit_string<E>(self, v: String) -> std::result::Result<Value, E> {
+        Ok(Value::Bytes(v.into_bytes()))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<Value, E> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut list = Vec::new();
+        while let Some(elem) = seq.next_element()? {
+            list.push(elem);
+        }
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> std::result::Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut dict = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            match key {
+                Value::Bytes(key) => {
+                    dict.insert(key, value);
+                }
+                _ => {
+                    return Err(A::Error::custom("dictionary key must be a byte string"));
+                }
+            }
+        }
+        Ok(Value::Dict(dict))
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+// Convert any `Serialize` type into a `Value`.
+pub fn to_value<T>(value: T) -> Result<Value>
+where
+    T: Serialize,
+{
+    value.serialize(ser::Serializer::default())
+}
+
+// Interpret a `Value` as an instance of type `T`.
+pub fn from_value<T>(value: Value) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(value)
+}
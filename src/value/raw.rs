@@ -0,0 +1,155 @@
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::error::Result;
+use crate::value::KeyBytes;
+
+// Name of the newtype struct `RawValue` pretends to be. The serializers and
+// deserializers in this crate recognize it and copy bytes verbatim instead of
+// going through the data model; everything else sees an ordinary newtype
+// around a byte string.
+pub(crate) const RAW_VALUE_TOKEN: &str = "$serde_bencode::private::RawValue";
+
+// The encoded bytes of a single bencode value, kept exactly as they appeared
+// in the input. The value is not decoded, but it is held to the same rules as
+// one that is: canonical integers, byte string keys each with a value, and the
+// configured limits. Serializing it writes the same bytes back out.
+//
+// Use `&'de RawValue` as a field type to borrow from a slice, or
+// `Box<RawValue>` to own the bytes, which is also what works with
+// `from_reader`. A typical use is the infohash of a torrent, which is the SHA-1
+// of the original bytes of its `info` dictionary:
+//
+//     #[derive(Deserialize)]
+//     struct Torrent<'a> {
+//         #[serde(borrow)]
+//         info: &'a RawValue,
+//     }
+//
+// Raw values can't be used inside `#[serde(flatten)]` or untagged enums, as
+// those buffer the input before it reaches the field.
+#[repr(transparent)]
+pub struct RawValue {
+    bencode: [u8],
+}
+
+impl RawValue {
+    fn from_borrowed(bencode: &[u8]) -> &Self {
+        // `RawValue` is a transparent wrapper around `[u8]`.
+        unsafe { &*(bencode as *const [u8] as *const RawValue) }
+    }
+
+    fn from_owned(bencode: Box<[u8]>) -> Box<Self> {
+        unsafe { Box::from_raw(Box::into_raw(bencode) as *mut RawValue) }
+    }
+
+    // Check that `bencode` holds exactly one well-formed value and wrap it
+    // without re-encoding.
+    pub fn from_bytes(bencode: Vec<u8>) -> Result<Box<Self>> {
+        crate::from_bytes::<de::IgnoredAny>(&bencode)?;
+        Ok(RawValue::from_owned(bencode.into_boxed_slice()))
+    }
+
+    // The encoded bytes, e.g. to hash them.
+    pub fn get(&self) -> &[u8] {
+        &self.bencode
+    }
+}
+
+impl Clone for Box<RawValue> {
+    fn clone(&self) -> Self {
+        (**self).to_owned()
+    }
+}
+
+impl ToOwned for RawValue {
+    type Owned = Box<RawValue>;
+
+    fn to_owned(&self) -> Box<RawValue> {
+        RawValue::from_owned(self.bencode.to_vec().into_boxed_slice())
+    }
+}
+
+impl PartialEq for RawValue {
+    fn eq(&self, other: &RawValue) -> bool {
+        self.bencode == other.bencode
+    }
+}
+
+impl fmt::Debug for RawValue {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_tuple("RawValue")
+            .field(&String::from_utf8_lossy(&self.bencode))
+            .finish()
+    }
+}
+
+impl Serialize for RawValue {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(RAW_VALUE_TOKEN, &KeyBytes(&self.bencode))
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for &'a RawValue {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ReferenceVisitor;
+
+        impl<'de> Visitor<'de> for ReferenceVisitor {
+            type Value = &'de RawValue;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a bencode value borrowed from the input")
+            }
+
+            fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> std::result::Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(RawValue::from_borrowed(v))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(RAW_VALUE_TOKEN, ReferenceVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Box<RawValue> {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BoxedVisitor;
+
+        impl<'de> Visitor<'de> for BoxedVisitor {
+            type Value = Box<RawValue>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a bencode value")
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(RawValue::from_owned(v.to_vec().into_boxed_slice()))
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(RawValue::from_owned(v.into_boxed_slice()))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(RAW_VALUE_TOKEN, BoxedVisitor)
+    }
+}
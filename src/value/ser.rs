@@ -0,0 +1,373 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use serde::{ser, Serialize};
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::ser::MapKeySerializer;
+use crate::value::{Value, RAW_VALUE_TOKEN};
+
+// Serializer whose output is a `Value` instead of encoded bytes. Used by
+// `to_value`.
+#[derive(Default)]
+pub struct Serializer<'a> {
+    // Only set while serializing a dictionary value, where a `None` is
+    // reported through it so that the entry can be left out.
+    none: Option<&'a Cell<bool>>,
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeDict;
+    type SerializeStruct = SerializeDict;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(Value::Int(i64::from(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value> {
+        Ok(Value::Int(v))
+    }
+
+    // `Value::Int` is an `i64`, so wider integers are only accepted when they
+    // happen to fit.
+    fn serialize_i128(self, v: i128) -> Result<Value> {
+        i64::try_from(v).map(Value::Int).map_err(|_| Error::from(ErrorKind::IntegerOverflow))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        i64::try_from(v).map(Value::Int).map_err(|_| Error::from(ErrorKind::IntegerOverflow))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value> {
+        i64::try_from(v).map(Value::Int).map_err(|_| Error::from(ErrorKind::IntegerOverflow))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Value> {
+        Err(ErrorKind::FloatUnsupported.into())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Value> {
+        Err(ErrorKind::FloatUnsupported.into())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    // The returned value is thrown away when `none` is set.
+    fn serialize_none(self) -> Result<Value> {
+        match self.none {
+            Some(none) => {
+                none.set(true);
+                Ok(Value::List(Vec::new()))
+            }
+            None => Err(ErrorKind::UnexpectedNone.into()),
+        }
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    // Same representation as on the wire: an empty list.
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(Value::List(Vec::new()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str,
+                              _variant_index: u32,
+                              variant: &'static str) -> Result<Value> {
+        self.serialize_str(variant)
+    }
+
+    // The bytes of a `RawValue` are decoded, so the result is the same as if
+    // the value had never been kept raw.
+    fn serialize_newtype_struct<T>(self, name: &'static str,
+                                   value: &T) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        if name == RAW_VALUE_TOKEN {
+            let raw = value.serialize(MapKeySerializer)?;
+            return crate::from_bytes(&raw);
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(self, _name: &'static str,
+                                    _variant_index: u32,
+                                    variant: &'static str,
+                                    value: &T) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        Ok(tagged(variant, value.serialize(Serializer::default())?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList> {
+        Ok(SerializeList {
+            list: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str,
+                              len: usize) -> Result<SerializeList> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str,
+                               _variant_index: u32,
+                               variant: &'static str,
+                               len: usize) -> Result<SerializeTupleVariant> {
+        Ok(SerializeTupleVariant {
+            variant,
+            list: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeDict> {
+        Ok(SerializeDict {
+            dict: BTreeMap::new(),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str,
+                        len: usize) -> Result<SerializeDict> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str,
+                                _variant_index: u32,
+                                variant: &'static str,
+                                _len: usize) -> Result<SerializeStructVariant> {
+        Ok(SerializeStructVariant {
+            variant,
+            dict: BTreeMap::new(),
+        })
+    }
+}
+
+pub struct SerializeList {
+    list: Vec<Value>,
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.list.push(value.serialize(Serializer::default())?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::List(self.list))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeTupleVariant {
+    variant: &'static str,
+    list: Vec<Value>,
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.list.push(value.serialize(Serializer::default())?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(tagged(self.variant, Value::List(self.list)))
+    }
+}
+
+pub struct SerializeDict {
+    dict: BTreeMap<Vec<u8>, Value>,
+    next_key: Option<Vec<u8>>,
+}
+
+fn insert_entry<T>(dict: &mut BTreeMap<Vec<u8>, Value>, key: Vec<u8>, value: &T) -> Result<()>
+where
+    T: ?Sized + Serialize,
+{
+    let none = Cell::new(false);
+    let value = value.serialize(Serializer { none: Some(&none) })?;
+    // An absent key is how a dictionary says `None`.
+    if none.get() {
+        return Ok(());
+    }
+
+    match dict.insert(key, value) {
+        Some(_) => Err(ErrorKind::DuplicateKey.into()),
+        None => Ok(()),
+    }
+}
+
+impl ser::SerializeMap for SerializeDict {
+    type Ok = Value;
+    type Error = Error;
+
+    // Keys are restricted exactly like they are when encoding to bytes.
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.next_key = Some(key.serialize(MapKeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = self.next_key.take();
+        // Panic because this indicates a bug in the program rather than an
+        // expected failure.
+        let key = key.expect("serialize_value called before serialize_key");
+        insert_entry(&mut self.dict, key, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Dict(self.dict))
+    }
+}
+
+impl ser::SerializeStruct for SerializeDict {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        insert_entry(&mut self.dict, key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Dict(self.dict))
+    }
+}
+
+pub struct SerializeStructVariant {
+    variant: &'static str,
+    dict: BTreeMap<Vec<u8>, Value>,
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        insert_entry(&mut self.dict, key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(tagged(self.variant, Value::Dict(self.dict)))
+    }
+}
+
+// Non-unit enum variants are single-entry dictionaries keyed by the variant
+// name.
+fn tagged(variant: &'static str, value: Value) -> Value {
+    let mut dict = BTreeMap::new();
+    dict.insert(variant.as_bytes().to_vec(), value);
+    Value::Dict(dict)
+}